@@ -22,21 +22,31 @@ async fn main() -> Result<()> {
         email_address,
         password,
         watch_spam: false,
+        watch_folders: Vec::new(),
         show_name: true,
         show_subject: true,
         show_body: true,
+        show_headers: false,
+        totp_secret: None,
+        endpoint: Default::default(),
+        cache_dir: None,
     };
 
-    let client = Client::new(&config).await?;
+    let client = Client::new_with_second_factor(&config, || {
+        let mut code = String::new();
+        println!("Please enter TOTP code: ");
+        std::io::stdin().read_line(&mut code).ok()?;
+        Some(code.trim().to_string())
+    })
+    .await?;
 
-    let mails = client.get_mails();
-    pin_mut!(mails);
-    while let Some(mail) = mails.next().await {
-        let mut mail = mail?;
-        if mail.read_status == ReadStatus::Read {
-            continue;
-        }
-        let decrypted_mail = client.decrypt(&mail).await;
+    let mails = client.get_mails().filter(|mail| {
+        std::future::ready(!matches!(mail, Ok(m) if m.read_status == ReadStatus::Read))
+    });
+    let decrypted = client.decrypt_stream(mails, 8);
+    pin_mut!(decrypted);
+    while let Some(decrypted) = decrypted.next().await {
+        let (mut mail, decrypted_mail) = decrypted?;
         info!("Got mail: {:?}", decrypted_mail);
         client.set_read_status(&mut mail, ReadStatus::Read).await?;
     }
@@ -1,9 +1,9 @@
 use anyhow::Result;
 use futures_util::pin_mut;
 use futures_util::StreamExt;
-use tracing::{info, warn};
+use tracing::info;
 use tuta_poll::client::Client;
-use tuta_poll::types::ReadStatus;
+use tuta_poll::types::{EntityEvent, OperationType, ReadStatus};
 use tuta_poll::*;
 
 #[tokio::main]
@@ -22,37 +22,39 @@ async fn main() -> Result<()> {
         email_address,
         password,
         watch_spam: false,
+        watch_folders: Vec::new(),
         show_name: true,
         show_subject: true,
         show_body: true,
+        show_headers: false,
+        totp_secret: None,
+        endpoint: Default::default(),
+        cache_dir: None,
     };
 
     let client = Client::new(&config).await?;
-    let connector = client.get_websocket_connector()?;
 
-    loop {
-        info!("Connecting to websocket");
-        let mut socket = connector.connect()?;
+    let events = client.events();
+    pin_mut!(events);
+    while let Some(event) = events.next().await {
+        let EntityEvent::Mail {
+            operation: OperationType::Create,
+            id,
+        } = event?
+        else {
+            continue;
+        };
+        if !client.is_watched(&id) {
+            continue;
+        }
 
-        while let Ok(has_new) = socket.has_new().await {
-            if !has_new {
-                continue;
-            }
-            let mails = client.get_mails();
-            pin_mut!(mails);
-            while let Some(mail) = mails.next().await {
-                let mut mail = mail?;
-                if mail.read_status == ReadStatus::Read {
-                    continue;
-                }
-                let decrypted_mail = client.decrypt(&mail).await;
-                info!("Got mail: {:?}", decrypted_mail);
-                client.set_read_status(&mut mail, ReadStatus::Read).await?;
-            }
+        let mut mail = client.get_mail(&id).await?;
+        if mail.read_status == ReadStatus::Read {
+            continue;
         }
-        warn!("Error getting mails. Retrying in 10s");
-        std::thread::sleep(std::time::Duration::from_secs(10));
+        let decrypted_mail = client.decrypt(&mail).await;
+        info!("Got mail: {:?}", decrypted_mail);
+        client.set_read_status(&mut mail, ReadStatus::Read).await?;
     }
-    // socket.close(None);
-    // Ok(())
+    Ok(())
 }
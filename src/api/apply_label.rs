@@ -0,0 +1,39 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::IdTuple;
+use crate::Result;
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    mails: &'a [IdTuple],
+    added_labels: &'a [IdTuple],
+    removed_labels: &'a [IdTuple],
+}
+
+/// Adds `added_labels` to and removes `removed_labels` from `mails`.
+///
+/// Sends a single request, so `mails` has to be chunked to [`super::MAX_BATCH_MAILS`] first.
+pub async fn send(
+    client: &HttpClient,
+    mails: &[IdTuple],
+    added_labels: &[IdTuple],
+    removed_labels: &[IdTuple],
+) -> Result<()> {
+    debug!("Changing labels of {} mails", mails.len());
+    let payload = serde_json::to_string(&Request {
+        format: (),
+        mails,
+        added_labels,
+        removed_labels,
+    })?;
+
+    let url = client.url("/rest/tutanota/applylabelservice")?;
+    client.send(Method::AuthPost, url, Some(payload)).await?;
+    debug!("Changed labels");
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::{ArchiveDataType, Base64, Id, IdTuple};
+use crate::{Error, Result};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use url::Url;
+
+/// Token which grants access to the blobs of an instance and the servers which store them.
+#[derive(Debug, Clone)]
+pub struct BlobAccess {
+    pub archive_id: Id,
+    pub token: String,
+    pub servers: Vec<Url>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenRequest<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    #[serde(with = "string_to_enum")]
+    archive_data_type: ArchiveDataType,
+    read: ReadRequest<'a>,
+    write: (),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadRequest<'a> {
+    #[serde(rename = "_id")]
+    id: String,
+    archive_id: &'a Id,
+    instance_list_id: &'a Id,
+    instance_ids: Vec<InstanceId<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceId<'a> {
+    #[serde(rename = "_id")]
+    id: String,
+    instance_id: &'a Id,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponse {
+    #[serde(rename = "_format", with = "serde_format")]
+    _format: (),
+    blob_access_info: BlobAccessInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlobAccessInfo {
+    blob_access_token: String,
+    servers: Vec<BlobServer>,
+}
+
+#[derive(Deserialize)]
+struct BlobServer {
+    url: Url,
+}
+
+/// Requests a token to read the blobs of `instance` in the archive `archive_id`, which stores
+/// data of type `archive_data_type`.
+pub async fn fetch_read_access(
+    client: &HttpClient,
+    archive_data_type: ArchiveDataType,
+    archive_id: &Id,
+    instance: &IdTuple,
+) -> Result<BlobAccess> {
+    debug!("Fetching blob access token");
+    let payload = serde_json::to_string(&TokenRequest {
+        format: (),
+        archive_data_type,
+        read: ReadRequest {
+            id: super::aggregate_id(),
+            archive_id,
+            instance_list_id: &instance.0,
+            instance_ids: vec![InstanceId {
+                id: super::aggregate_id(),
+                instance_id: &instance.1,
+            }],
+        },
+        write: (),
+    })?;
+
+    let url = client.url("/rest/storage/blobaccesstokenservice")?;
+    let response = client
+        .send(Method::AuthPost, url, Some(payload))
+        .await?
+        .json::<TokenResponse>()
+        .await?;
+
+    debug!("Fetched blob access token");
+    let info = response.blob_access_info;
+    Ok(BlobAccess {
+        archive_id: archive_id.clone(),
+        token: info.blob_access_token,
+        servers: info.servers.into_iter().map(|server| server.url).collect(),
+    })
+}
+
+/// Downloads the still encrypted blob `blob_id`.
+pub async fn fetch(client: &HttpClient, access: &BlobAccess, blob_id: &Id) -> Result<Base64> {
+    debug!("Fetching blob");
+    let body = serde_json::json!({
+        "_format": "0",
+        "archiveId": access.archive_id,
+        "blobId": blob_id,
+        "blobIds": [],
+    })
+    .to_string();
+
+    let response = send(
+        client,
+        access,
+        "/rest/storage/blobservice",
+        &[("_body", &body)],
+    )
+    .await?;
+    let blob = response.bytes().await?.to_vec();
+    debug!("Fetched blob of {} bytes", blob.len());
+    Ok(blob)
+}
+
+/// Sends a GET request for `path` to the blob servers of `access`, trying every server in turn.
+pub async fn send(
+    client: &HttpClient,
+    access: &BlobAccess,
+    path: &str,
+    query: &[(&str, &str)],
+) -> Result<Response> {
+    let mut last_error = Error::MissingData("No blob server available".into());
+    for server in &access.servers {
+        let mut url = server.join(path)?;
+        url.query_pairs_mut()
+            .extend_pairs(query)
+            .append_pair("blobAccessToken", &access.token);
+
+        match client.send(Method::Get, url, None).await {
+            Ok(response) => return Ok(response),
+            Err(e) => {
+                warn!("Request to blob server {} failed: {}", server, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
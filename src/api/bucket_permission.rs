@@ -1,12 +1,11 @@
 use crate::http_client::{HttpClient, Method};
 use crate::types::{BucketPermission, Id};
-use anyhow::Result;
+use crate::Result;
 use tracing::{debug, trace};
 
 pub async fn fetch(client: &HttpClient, bucket: &Id) -> Result<Vec<BucketPermission>> {
     debug!("Fetching bucket permission");
-    let mut url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/sys/bucketpermission/{}", bucket).as_str())?;
+    let mut url = client.url(&format!("/rest/sys/bucketpermission/{}", bucket))?;
     url.query_pairs_mut()
         .append_pair("start", "------------")
         .append_pair("count", "1000")
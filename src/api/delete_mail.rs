@@ -0,0 +1,33 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::IdTuple;
+use crate::Result;
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    mails: &'a [IdTuple],
+    folder: &'a IdTuple,
+}
+
+/// Permanently deletes `mails` from `folder`.
+///
+/// The server rejects more than [`super::MAX_BATCH_MAILS`] mails, larger sets have to be split
+/// by the caller.
+pub async fn send(client: &HttpClient, mails: &[IdTuple], folder: &IdTuple) -> Result<()> {
+    debug!("Deleting {} mails", mails.len());
+    let payload = serde_json::to_string(&Request {
+        format: (),
+        mails,
+        folder,
+    })?;
+
+    let url = client.url("/rest/tutanota/maildeleteservice")?;
+    client.send(Method::AuthDelete, url, Some(payload)).await?;
+    debug!("Deleted mails");
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::http_client::{HttpClient, Method};
+use crate::types::{EntityEventBatch, Id};
+use crate::Result;
+use tracing::{debug, trace};
+
+const BATCH_COUNT: usize = 1000;
+
+/// Fetches all entity event batches of `group` which are newer than `start`.
+pub async fn fetch_since(
+    client: &HttpClient,
+    group: &Id,
+    start: &Id,
+) -> Result<Vec<EntityEventBatch>> {
+    debug!("Fetching entity event batches");
+    let mut batches = Vec::new();
+    let mut start = start.clone();
+    loop {
+        let curr_batches = fetch(client, group, &start, BATCH_COUNT, false).await?;
+        let n = curr_batches.len();
+        if let Some(last) = curr_batches.last() {
+            start = last.id.1.clone();
+        }
+        batches.extend(curr_batches);
+        if n < BATCH_COUNT {
+            break;
+        }
+    }
+
+    debug!("Fetched {} entity event batches", batches.len());
+    Ok(batches)
+}
+
+/// Fetches the id of the newest entity event batch of `group`.
+pub async fn fetch_last_id(client: &HttpClient, group: &Id) -> Result<Option<Id>> {
+    debug!("Fetching last entity event batch id");
+    let batches = fetch(client, group, &"zzzzzzzzzzzz".to_string(), 1, true).await?;
+    Ok(batches.into_iter().next().map(|batch| batch.id.1))
+}
+
+async fn fetch(
+    client: &HttpClient,
+    group: &Id,
+    start: &Id,
+    count: usize,
+    reverse: bool,
+) -> Result<Vec<EntityEventBatch>> {
+    let mut url = client.url(&format!("/rest/sys/entityeventbatch/{}", group))?;
+    url.query_pairs_mut()
+        .append_pair("start", start)
+        .append_pair("count", &count.to_string())
+        .append_pair("reverse", &reverse.to_string());
+
+    let batches = client
+        .send(Method::AuthGet, url, None)
+        .await?
+        .json::<Vec<EntityEventBatch>>()
+        .await?;
+
+    trace!("entity event batches: {:#?}", batches);
+    Ok(batches)
+}
@@ -0,0 +1,18 @@
+use crate::http_client::{HttpClient, Method};
+use crate::types::{File, IdTuple};
+use crate::Result;
+use tracing::{debug, trace};
+
+pub async fn fetch(client: &HttpClient, id: &IdTuple) -> Result<File> {
+    debug!("Fetching file");
+    let url = client.url(&format!("/rest/tutanota/file/{}/{}", id.0, id.1))?;
+
+    let file = client
+        .send(Method::AuthGet, url, None)
+        .await?
+        .json::<File>()
+        .await?;
+    debug!("Fetched file");
+    trace!("file: {:#?}", file);
+    Ok(file)
+}
@@ -1,12 +1,11 @@
 use crate::http_client::{HttpClient, Method};
 use crate::types::{GroupInfo, IdTuple};
-use anyhow::Result;
+use crate::Result;
 use tracing::{debug, trace};
 
 pub async fn fetch(client: &HttpClient, group: &IdTuple) -> Result<GroupInfo> {
     debug!("Fetching groupinfo");
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/sys/groupinfo/{}/{}", group.0, group.1).as_str())?;
+    let url = client.url(&format!("/rest/sys/groupinfo/{}/{}", group.0, group.1))?;
 
     let group_info = client
         .send(Method::AuthGet, url, None)
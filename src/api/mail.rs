@@ -1,6 +1,6 @@
 use crate::http_client::{HttpClient, Method};
 use crate::types::{Id, Mail};
-use anyhow::Result;
+use crate::Result;
 use tracing::{debug, trace};
 
 pub async fn fetch_from_inbox(
@@ -9,8 +9,7 @@ pub async fn fetch_from_inbox(
     start: Option<Id>,
 ) -> Result<Vec<Mail>> {
     debug!("Fetching mails");
-    let mut url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mail/{}", mails).as_str())?;
+    let mut url = client.url(&format!("/rest/tutanota/mail/{}", mails))?;
     url.query_pairs_mut()
         .append_pair("count", "100")
         .append_pair("reverse", "true");
@@ -37,8 +36,10 @@ pub async fn fetch_from_id(
     instance_id: &str,
 ) -> Result<Mail> {
     debug!("Fetching single mail");
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mail/{}/{}", instance_list_id, instance_id).as_str())?;
+    let url = client.url(&format!(
+        "/rest/tutanota/mail/{}/{}",
+        instance_list_id, instance_id
+    ))?;
 
     let mail = client
         .send(Method::AuthGet, url, None)
@@ -51,8 +52,7 @@ pub async fn fetch_from_id(
 }
 
 pub async fn update(client: &HttpClient, mail: &Mail) -> Result<()> {
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mail/{}/{}", mail.id.0, mail.id.1).as_str())?;
+    let url = client.url(&format!("/rest/tutanota/mail/{}/{}", mail.id.0, mail.id.1))?;
 
     // if update_key {
     //     url.query_pairs_mut()
@@ -0,0 +1,31 @@
+use crate::api::blob::{self, BlobAccess};
+use crate::http_client::HttpClient;
+use crate::types::{IdTuple, MailDetailsBlob};
+use crate::{Error, Result};
+use tracing::{debug, trace};
+
+/// Fetches the mail details `id` from the blob store, `access` has to grant access to them.
+pub async fn fetch(
+    client: &HttpClient,
+    access: &BlobAccess,
+    id: &IdTuple,
+) -> Result<MailDetailsBlob> {
+    debug!("Fetching mail details");
+    let response = blob::send(
+        client,
+        access,
+        &format!("/rest/tutanota/maildetailsblob/{}", id.0),
+        &[("ids", &id.1)],
+    )
+    .await?;
+
+    let details = response
+        .json::<Vec<MailDetailsBlob>>()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::MissingData(format!("Mail details {:?} not found", id)))?;
+    debug!("Fetched mail details");
+    trace!("mail details: {:#?}", details);
+    Ok(details)
+}
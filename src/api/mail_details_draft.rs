@@ -0,0 +1,23 @@
+use crate::http_client::{HttpClient, Method};
+use crate::types::{IdTuple, MailDetailsDraft};
+use crate::Result;
+use tracing::{debug, trace};
+
+/// Fetches the mail details `id` of a draft, which are a regular entity instead of a blob.
+pub async fn fetch(client: &HttpClient, id: &IdTuple) -> Result<MailDetailsDraft> {
+    debug!("Fetching mail details draft");
+    let url = client.url(&format!(
+        "/rest/tutanota/maildetailsdraft/{}/{}",
+        id.0, id.1
+    ))?;
+
+    let draft = client
+        .send(Method::AuthGet, url, None)
+        .await?
+        .json::<MailDetailsDraft>()
+        .await?;
+
+    debug!("Fetched mail details draft");
+    trace!("mail details draft: {:#?}", draft);
+    Ok(draft)
+}
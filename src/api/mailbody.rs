@@ -1,11 +1,12 @@
 use crate::http_client::{HttpClient, Method};
 use crate::serialize::*;
 use crate::types::Base64;
-use anyhow::Result;
+use crate::Result;
 use serde::Deserialize;
 use tracing::{debug, trace};
 
 #[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Mailbody {
     #[serde(with = "serde_format")]
     _format: (),
@@ -15,8 +16,7 @@ pub struct Mailbody {
 
 pub async fn fetch(client: &HttpClient, body: &str) -> Result<Vec<u8>> {
     debug!("Fetching body");
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mailbody/{}", body).as_str())?;
+    let url = client.url(&format!("/rest/tutanota/mailbody/{}", body))?;
 
     let text = client
         .send(Method::AuthGet, url, None)
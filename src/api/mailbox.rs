@@ -1,6 +1,6 @@
 use crate::http_client::{HttpClient, Method};
 use crate::serialize::*;
-use anyhow::Result;
+use crate::Result;
 use serde::Deserialize;
 use tracing::{debug, trace};
 
@@ -18,8 +18,7 @@ struct Folders {
 
 pub async fn fetch(client: &HttpClient, mailbox: &str) -> Result<String> {
     debug!("Fetching mailbox");
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mailbox/{}", mailbox).as_str())?;
+    let url = client.url(&format!("/rest/tutanota/mailbox/{}", mailbox))?;
 
     let folders = client
         .send(Method::AuthGet, url, None)
@@ -1,6 +1,6 @@
 use crate::http_client::{HttpClient, Method};
 use crate::serialize::*;
-use anyhow::Result;
+use crate::Result;
 use serde::Deserialize;
 use tracing::{debug, trace};
 
@@ -13,8 +13,7 @@ struct Response {
 
 pub async fn fetch(client: &HttpClient, group: &str) -> Result<String> {
     debug!("Fetching mailboxgrouproot");
-    let url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mailboxgrouproot/{}", group).as_str())?;
+    let url = client.url(&format!("/rest/tutanota/mailboxgrouproot/{}", group))?;
 
     let mailbox = client
         .send(Method::AuthGet, url, None)
@@ -1,12 +1,12 @@
 use crate::http_client::{HttpClient, Method};
-use crate::types::Folder;
-use anyhow::Result;
+use crate::types::{Base64, Folder, IdTuple};
+use crate::{Error, Result};
+use base64::{engine::general_purpose as engines, Engine as _};
 use tracing::{debug, trace};
 
 pub async fn fetch(client: &HttpClient, folders: &str) -> Result<Vec<Folder>> {
     debug!("Fetching mailfolder");
-    let mut url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/tutanota/mailfolder/{}", folders).as_str())?;
+    let mut url = client.url(&format!("/rest/tutanota/mailfolder/{}", folders))?;
     url.query_pairs_mut()
         .append_pair("start", "------------")
         .append_pair("count", "1000")
@@ -22,3 +22,35 @@ pub async fn fetch(client: &HttpClient, folders: &str) -> Result<Vec<Folder>> {
     trace!("mailfolder: {:#?}", folders);
     Ok(folders)
 }
+
+/// Replaces the encrypted name and color of the label `label`.
+///
+/// The entity is updated as a whole, so all other fields are sent back as they are.
+pub async fn update_label(
+    client: &HttpClient,
+    label: &IdTuple,
+    name: &Base64,
+    color: &Base64,
+) -> Result<()> {
+    debug!("Updating label");
+    let url = client.url(&format!(
+        "/rest/tutanota/mailfolder/{}/{}",
+        label.0, label.1
+    ))?;
+    let mut entity = client
+        .send(Method::AuthGet, url.clone(), None)
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    let fields = entity
+        .as_object_mut()
+        .ok_or_else(|| Error::InvalidData("Label is not an object".into()))?;
+    fields.insert("name".into(), engines::STANDARD.encode(name).into());
+    fields.insert("color".into(), engines::STANDARD.encode(color).into());
+
+    let payload = serde_json::to_string(&entity)?;
+    client.send(Method::AuthPut, url, Some(payload)).await?;
+    debug!("Updated label");
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::EncryptedHeaders;
+use crate::Result;
+use serde::Deserialize;
+use tracing::{debug, trace};
+
+#[derive(Debug, Deserialize)]
+struct Mailheaders {
+    #[serde(with = "serde_format")]
+    _format: (),
+    #[serde(flatten)]
+    headers: EncryptedHeaders,
+}
+
+pub async fn fetch(client: &HttpClient, headers: &str) -> Result<EncryptedHeaders> {
+    debug!("Fetching headers");
+    let url = client.url(&format!("/rest/tutanota/mailheaders/{}", headers))?;
+
+    let headers = client
+        .send(Method::AuthGet, url, None)
+        .await?
+        .json::<Mailheaders>()
+        .await?
+        .headers;
+
+    debug!("Fetched headers");
+    trace!("headers: {:?}", headers);
+    Ok(headers)
+}
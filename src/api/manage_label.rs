@@ -0,0 +1,73 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::{Aes128Key, Base64, Id, IdTuple};
+use crate::Result;
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateRequest<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    owner_group: &'a Id,
+    #[serde(with = "serde_base64_16")]
+    owner_enc_session_key: Aes128Key,
+    owner_key_version: &'a str,
+    data: LabelData,
+}
+
+#[derive(Serialize)]
+struct LabelData {
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(with = "serde_base64")]
+    name: Base64,
+    #[serde(with = "serde_base64")]
+    color: Base64,
+}
+
+#[derive(Serialize)]
+struct DeleteRequest<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    label: &'a IdTuple,
+}
+
+/// Creates a label in the mailbox of `owner_group`, `name` and `color` are encrypted with the
+/// session key.
+pub async fn create(
+    client: &HttpClient,
+    owner_group: &Id,
+    owner_enc_session_key: Aes128Key,
+    name: &Base64,
+    color: &Base64,
+) -> Result<()> {
+    debug!("Creating label");
+    let payload = serde_json::to_string(&CreateRequest {
+        format: (),
+        owner_group,
+        owner_enc_session_key,
+        owner_key_version: "0",
+        data: LabelData {
+            id: super::aggregate_id(),
+            name: name.clone(),
+            color: color.clone(),
+        },
+    })?;
+
+    let url = client.url("/rest/tutanota/managelabelservice")?;
+    client.send(Method::AuthPost, url, Some(payload)).await?;
+    debug!("Created label");
+    Ok(())
+}
+
+pub async fn delete(client: &HttpClient, label: &IdTuple) -> Result<()> {
+    debug!("Deleting label");
+    let payload = serde_json::to_string(&DeleteRequest { format: (), label })?;
+
+    let url = client.url("/rest/tutanota/managelabelservice")?;
+    client.send(Method::AuthDelete, url, Some(payload)).await?;
+    debug!("Deleted label");
+    Ok(())
+}
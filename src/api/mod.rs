@@ -1,17 +1,38 @@
-pub const BASE_URL: &'static str = "https://app.tuta.com";
-pub const CLIENT: &'static str = "Firefox Browser";
-pub const CLIENT_VERSION: &'static str = "3.119.6";
-pub const MODEL_VERSION: &'static str = "91.65";
+pub const BASE_URL: &str = "https://app.tuta.com";
+pub const CLIENT: &str = "Firefox Browser";
+pub const CLIENT_VERSION: &str = "3.119.6";
+pub const MODEL_VERSION: &str = "91.65";
+/// Maximum number of mails the mail services (move, delete, unread state, labels) accept in a
+/// single request.
+pub const MAX_BATCH_MAILS: usize = 50;
 
+pub mod apply_label;
+pub mod blob;
 pub mod bucket_permission;
+pub mod delete_mail;
+pub mod entity_event_batch;
+pub mod file;
 pub mod group;
 pub mod group_info;
 pub mod mail;
+pub mod mail_details;
+pub mod mail_details_draft;
 pub mod mailbody;
 pub mod mailbox;
 pub mod mailboxgrouproot;
 pub mod mailfolder;
+pub mod mailheaders;
+pub mod manage_label;
+pub mod move_mail;
 pub mod permission;
 pub mod salt;
+pub mod second_factor;
 pub mod session;
+pub mod unread_mail_state;
 pub mod user;
+
+/// Aggregates need an id which is unique within the request.
+fn aggregate_id() -> String {
+    use base64::{engine::general_purpose as engines, Engine as _};
+    engines::URL_SAFE_NO_PAD.encode(rand::random::<[u8; 4]>())
+}
@@ -0,0 +1,32 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::IdTuple;
+use crate::Result;
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    mails: &'a [IdTuple],
+    target_folder: &'a IdTuple,
+}
+
+/// Moves `mails` into the folder `target_folder`.
+///
+/// Callers must chunk `mails` to [`super::MAX_BATCH_MAILS`], this only sends one request.
+pub async fn send(client: &HttpClient, mails: &[IdTuple], target_folder: &IdTuple) -> Result<()> {
+    debug!("Moving {} mails", mails.len());
+    let payload = serde_json::to_string(&Request {
+        format: (),
+        mails,
+        target_folder,
+    })?;
+
+    let url = client.url("/rest/tutanota/movemailservice")?;
+    client.send(Method::AuthPost, url, Some(payload)).await?;
+    debug!("Moved mails");
+    Ok(())
+}
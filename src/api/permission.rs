@@ -1,12 +1,11 @@
 use crate::http_client::{HttpClient, Method};
 use crate::types::{Id, Permission};
-use anyhow::Result;
+use crate::Result;
 use tracing::{debug, trace};
 
 pub async fn fetch(client: &HttpClient, permission: &Id) -> Result<Vec<Permission>> {
     debug!("Fetching permission");
-    let mut url = url::Url::parse(super::BASE_URL)?
-        .join(format!("/rest/sys/permission/{}", permission).as_str())?;
+    let mut url = client.url(&format!("/rest/sys/permission/{}", permission))?;
     url.query_pairs_mut()
         .append_pair("start", "------------")
         .append_pair("count", "1000")
@@ -1,7 +1,7 @@
 use crate::http_client::{HttpClient, Method};
 use crate::serialize::*;
-use crate::types::Aes128Key;
-use anyhow::{Context, Result};
+use crate::types::{Aes128Key, KdfType, Salt};
+use crate::Result;
 use serde::Deserialize;
 use tracing::debug;
 
@@ -11,9 +11,11 @@ struct Response {
     _format: (),
     #[serde(with = "serde_base64_16")]
     salt: Aes128Key,
+    #[serde(with = "string_to_enum", rename = "kdfVersion", default)]
+    kdf_version: KdfType,
 }
 
-pub async fn fetch(client: &HttpClient, email_address: &str) -> Result<Aes128Key> {
+pub async fn fetch(client: &HttpClient, email_address: &str) -> Result<Salt> {
     debug!("Fetching salt");
 
     let payload = format!(
@@ -25,7 +27,7 @@ pub async fn fetch(client: &HttpClient, email_address: &str) -> Result<Aes128Key
     )
     .to_string();
 
-    let mut url = url::Url::parse(super::BASE_URL)?.join("/rest/sys/saltservice")?;
+    let mut url = client.url("/rest/sys/saltservice")?;
     url.set_query(Some(&payload));
 
     let response = client
@@ -35,5 +37,27 @@ pub async fn fetch(client: &HttpClient, email_address: &str) -> Result<Aes128Key
         .await?;
 
     debug!("Fetched salt");
-    response.salt.try_into().context("salt is too big")
+    Ok(Salt {
+        salt: response.salt,
+        kdf_version: response.kdf_version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_kdf_version() {
+        let response: Response = serde_json::from_str(
+            r#"{"_format":"0","salt":"AAECAwQFBgcICQoLDA0ODw==","kdfVersion":"1"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.salt, core::array::from_fn(|i| i as u8));
+        assert_eq!(response.kdf_version, KdfType::Argon2id);
+
+        let response: Response =
+            serde_json::from_str(r#"{"_format":"0","salt":"AAECAwQFBgcICQoLDA0ODw=="}"#).unwrap();
+        assert_eq!(response.kdf_version, KdfType::Bcrypt);
+    }
 }
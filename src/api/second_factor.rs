@@ -0,0 +1,72 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::IdTuple;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    otp_code: &'a str,
+    session: &'a IdTuple,
+    #[serde(rename = "type")]
+    factor_type: &'a str,
+    u2f: (),
+    webauthn: (),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    #[serde(rename = "_format", with = "serde_format")]
+    _format: (),
+    second_factor_pending: bool,
+}
+
+/// Type of the second factor as expected by the secondfactorauthservice.
+const SECOND_FACTOR_TYPE_TOTP: &str = "1";
+
+pub async fn send_totp(client: &HttpClient, session: &IdTuple, otp_code: &str) -> Result<()> {
+    debug!("Sending totp code");
+    let payload = serde_json::to_string(&Request {
+        format: (),
+        otp_code,
+        session,
+        factor_type: SECOND_FACTOR_TYPE_TOTP,
+        u2f: (),
+        webauthn: (),
+    })?;
+
+    let url = client.url("/rest/sys/secondfactorauthservice")?;
+    client.send(Method::Post, url, Some(payload)).await?;
+    debug!("Sent totp code");
+    Ok(())
+}
+
+/// Returns whether the session of `access_token` is still waiting for a second factor.
+pub async fn fetch_pending(client: &HttpClient, access_token: &str) -> Result<bool> {
+    debug!("Fetching second factor status");
+
+    let payload = format!(
+        "_body={}",
+        serde_json::json!({
+            "_format": "0",
+            "accessToken": access_token
+        })
+    );
+
+    let mut url = client.url("/rest/sys/secondfactorauthservice")?;
+    url.set_query(Some(&payload));
+
+    let response = client
+        .send(Method::Get, url, None)
+        .await?
+        .json::<Response>()
+        .await?;
+
+    debug!("Fetched second factor status");
+    Ok(response.second_factor_pending)
+}
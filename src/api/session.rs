@@ -1,9 +1,9 @@
 use crate::http_client::{HttpClient, Method};
 use crate::serialize::*;
-use crate::types::Session;
-use anyhow::Result;
+use crate::types::{Aes128Key, IdTuple, Session};
+use crate::{Error, Result};
 use base64::{engine::general_purpose as engines, Engine as _};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use tracing::{debug, trace};
 
@@ -12,7 +12,8 @@ use tracing::{debug, trace};
 struct Request<'a> {
     #[serde(rename = "_format", with = "serde_format")]
     format: (),
-    access_key: (),
+    #[serde(with = "serde_option_base64_16")]
+    access_key: Option<Aes128Key>,
     auth_token: (),
     auth_verifier: String,
     client_identifier: &'a str,
@@ -25,25 +26,26 @@ pub async fn fetch(
     client: &HttpClient,
     email_address: &str,
     user_passphrase_key: &[u8],
+    access_key: Option<&Aes128Key>,
 ) -> Result<Session> {
     debug!("Fetching session");
 
     let mut hasher = sha2::Sha256::new();
     hasher.update(user_passphrase_key);
     let hash = hasher.finalize();
-    let auth_verifier = engines::URL_SAFE_NO_PAD.encode(&hash);
+    let auth_verifier = engines::URL_SAFE_NO_PAD.encode(hash);
     let payload = serde_json::to_string(&Request {
         format: (),
-        access_key: (),
+        access_key: access_key.copied(),
         auth_token: (),
         auth_verifier,
-        client_identifier: crate::api::CLIENT,
+        client_identifier: &client.endpoint().client_identifier,
         mail_address: email_address,
         recover_code_verifier: (),
         user: (),
     })?;
 
-    let url = url::Url::parse(super::BASE_URL)?.join("/rest/sys/sessionservice")?;
+    let url = client.url("/rest/sys/sessionservice")?;
 
     let session = client
         .send(Method::Post, url, Some(payload))
@@ -54,3 +56,94 @@ pub async fn fetch(
     trace!("session: {:#?}", session);
     Ok(session)
 }
+
+#[derive(Deserialize)]
+struct SessionInstance {
+    #[serde(with = "serde_format")]
+    _format: (),
+    #[serde(rename = "accessKey", with = "serde_option_base64_16")]
+    access_key: Option<Aes128Key>,
+}
+
+/// Fetches the session belonging to the access token of `client` and returns its access key.
+///
+/// This fails if the access token is no longer valid, so it doubles as a cheap token check.
+pub async fn fetch_access_key(client: &HttpClient) -> Result<Option<Aes128Key>> {
+    debug!("Fetching session access key");
+    let access_token = client.get_access_token().ok_or(Error::NotAuthenticated)?;
+    let (list_id, element_id) = session_id(access_token)?;
+    let url = client.url(&format!("/rest/sys/session/{}/{}", list_id, element_id))?;
+
+    let access_key = client
+        .send(Method::AuthGet, url, None)
+        .await?
+        .json::<SessionInstance>()
+        .await?
+        .access_key;
+
+    debug!("Fetched session access key");
+    Ok(access_key)
+}
+
+pub async fn delete(client: &HttpClient) -> Result<()> {
+    debug!("Deleting session");
+    let access_token = client.get_access_token().ok_or(Error::NotAuthenticated)?;
+    let (list_id, element_id) = session_id(access_token)?;
+    let url = client.url(&format!("/rest/sys/session/{}/{}", list_id, element_id))?;
+
+    client.send(Method::AuthDelete, url, None).await?;
+    debug!("Deleted session");
+    Ok(())
+}
+
+const GENERATED_ID_BYTES_LENGTH: usize = 9;
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_EXT: &[u8] = b"-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+
+/// Derives the id of the session entity from an access token.
+///
+/// The first bytes of the token are the list id (in tuta's sortable base64 alphabet), the element
+/// id is the hash of the remaining bytes.
+pub fn session_id(access_token: &str) -> Result<IdTuple> {
+    let bytes = engines::URL_SAFE_NO_PAD
+        .decode(access_token.trim_end_matches('='))
+        .map_err(|_| Error::InvalidData("Access token is not valid base64".into()))?;
+    if bytes.len() <= GENERATED_ID_BYTES_LENGTH {
+        return Err(Error::InvalidData("Access token is too short".into()));
+    }
+
+    let list_id = engines::STANDARD
+        .encode(&bytes[..GENERATED_ID_BYTES_LENGTH])
+        .bytes()
+        .map(|c| {
+            let pos = BASE64.iter().position(|&b| b == c).unwrap_or_default();
+            BASE64_EXT[pos] as char
+        })
+        .collect();
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(&bytes[GENERATED_ID_BYTES_LENGTH..]);
+    let element_id = engines::URL_SAFE_NO_PAD.encode(hasher.finalize());
+    Ok((list_id, element_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_id_from_access_token() {
+        let (list_id, element_id) = session_id("ABCDEFGHIJKLYWJj").unwrap();
+        assert_eq!(list_id, "-0123456789A");
+        // SHA-256 of "abc"
+        assert_eq!(element_id, "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+    }
+
+    #[test]
+    fn session_id_rejects_short_token() {
+        assert!(matches!(
+            session_id("ABCDEFGHIJKL"),
+            Err(Error::InvalidData(_))
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::http_client::{HttpClient, Method};
+use crate::serialize::*;
+use crate::types::IdTuple;
+use crate::Result;
+use serde::Serialize;
+use tracing::debug;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    #[serde(rename = "_format", with = "serde_format")]
+    format: (),
+    mails: &'a [IdTuple],
+    unread: bool,
+}
+
+/// Marks `mails` as read or unread without sending the mails back.
+///
+/// `mails` must not exceed [`super::MAX_BATCH_MAILS`], the caller splits larger sets.
+pub async fn send(client: &HttpClient, mails: &[IdTuple], unread: bool) -> Result<()> {
+    debug!("Setting unread state of {} mails", mails.len());
+    let payload = serde_json::to_string(&Request {
+        format: (),
+        mails,
+        unread,
+    })?;
+
+    let url = client.url("/rest/tutanota/unreadmailstateservice")?;
+    client.send(Method::AuthPost, url, Some(payload)).await?;
+    debug!("Set unread state");
+    Ok(())
+}
@@ -1,13 +1,12 @@
 use crate::http_client::{HttpClient, Method};
 use crate::types::User;
-use anyhow::Result;
+use crate::Result;
 use tracing::{debug, trace};
 
 pub async fn fetch(client: &HttpClient, user: &str) -> Result<User> {
     debug!("Fetching user");
 
-    let url =
-        url::Url::parse(super::BASE_URL)?.join(format!("/rest/sys/user/{}", user).as_str())?;
+    let url = client.url(&format!("/rest/sys/user/{}", user))?;
 
     let user = client
         .send(Method::AuthGet, url, None)
@@ -0,0 +1,229 @@
+use crate::crypto;
+use crate::serialize::*;
+use crate::types::{Aes128Key, Base64, EncryptedHeaders, Id, IdTuple, Mail, MailDetails, Salt};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+const SALT_FILE: &str = "salt.json";
+const INDEX_FILE: &str = "index";
+const MAIL_DIR: &str = "mails";
+
+/// Everything known about a single mail.
+///
+/// The body and details are kept as they come from the server, i.e. still encrypted with the
+/// session key.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CachedMail {
+    pub mail: Mail,
+    #[serde(with = "serde_option_base64_16")]
+    pub session_key: Option<Aes128Key>,
+    #[serde(with = "serde_option_base64")]
+    pub body: Option<Base64>,
+    #[serde(default)]
+    pub details: Option<MailDetails>,
+    /// Legacy headers, headers of newer mails are part of the details.
+    #[serde(default)]
+    pub headers: Option<EncryptedHeaders>,
+}
+
+impl CachedMail {
+    pub fn new(mail: Mail) -> CachedMail {
+        CachedMail {
+            mail,
+            session_key: None,
+            body: None,
+            details: None,
+            headers: None,
+        }
+    }
+}
+
+/// What an offline client needs to know about the account.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheIndex {
+    pub user_id: Id,
+    pub inboxes: Vec<String>,
+}
+
+/// On-disk mail cache, every file except the salt is encrypted with the cache key.
+pub struct MailCache {
+    dir: PathBuf,
+    key: Aes128Key,
+}
+
+impl MailCache {
+    pub async fn open(dir: &Path, key: Aes128Key) -> Result<MailCache> {
+        tokio::fs::create_dir_all(dir.join(MAIL_DIR)).await?;
+        Ok(MailCache {
+            dir: dir.to_path_buf(),
+            key,
+        })
+    }
+
+    pub fn key(&self) -> &Aes128Key {
+        &self.key
+    }
+
+    /// The salt is stored in plain text, it is needed to derive the cache key offline.
+    pub async fn save_salt(dir: &Path, salt: &Salt) -> Result<()> {
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(dir.join(SALT_FILE), serde_json::to_vec(salt)?).await?;
+        Ok(())
+    }
+
+    pub async fn load_salt(dir: &Path) -> Result<Salt> {
+        let salt = tokio::fs::read(dir.join(SALT_FILE)).await?;
+        Ok(serde_json::from_slice(&salt)?)
+    }
+
+    pub async fn save_index(&self, index: &CacheIndex) -> Result<()> {
+        self.write(&self.dir.join(INDEX_FILE), index).await
+    }
+
+    pub async fn load_index(&self) -> Result<CacheIndex> {
+        self.read(&self.dir.join(INDEX_FILE))
+            .await?
+            .ok_or_else(|| Error::MissingData("Cache has no index".into()))
+    }
+
+    pub async fn get(&self, id: &IdTuple) -> Result<Option<CachedMail>> {
+        self.read(&self.mail_path(id)).await
+    }
+
+    pub async fn put(&self, entry: &CachedMail) -> Result<()> {
+        self.write(&self.mail_path(&entry.mail.id), entry).await
+    }
+
+    pub async fn remove(&self, id: &IdTuple) -> Result<()> {
+        match tokio::fs::remove_file(self.mail_path(id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns all cached mails of the mail list `mails`, newest first.
+    pub async fn list(&self, mails: &str) -> Result<Vec<CachedMail>> {
+        let mut ids = Vec::new();
+        let mut entries = tokio::fs::read_dir(self.dir.join(MAIL_DIR)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some((list, element)) = file_name.to_str().and_then(|n| n.split_once('.')) else {
+                continue;
+            };
+            if list == mails {
+                ids.push((list.to_string(), element.to_string()));
+            }
+        }
+        ids.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let mut cached = Vec::with_capacity(ids.len());
+        for id in ids {
+            match self.get(&id).await {
+                Ok(Some(entry)) => cached.push(entry),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping unreadable cache entry {:?}: {}", id, e),
+            }
+        }
+        Ok(cached)
+    }
+
+    fn mail_path(&self, id: &IdTuple) -> PathBuf {
+        self.dir.join(MAIL_DIR).join(format!("{}.{}", id.0, id.1))
+    }
+
+    async fn read<T: serde::de::DeserializeOwned>(&self, path: &Path) -> Result<Option<T>> {
+        let data = match tokio::fs::read(path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        debug!("Read {} from cache", path.display());
+        let data = crypto::aes_decrypt(&self.key, &data)?;
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+
+    async fn write<T: Serialize>(&self, path: &Path, value: &T) -> Result<()> {
+        let data = crypto::aes_encrypt(&self.key, &serde_json::to_vec(value)?)?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::types::tests::mail;
+
+    /// A fresh directory below the system temp dir.
+    pub(crate) fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tuta_poll-{:016x}", rand::random::<u64>()))
+    }
+
+    #[tokio::test]
+    async fn put_get_list() {
+        let dir = temp_dir();
+        let cache = MailCache::open(&dir, crypto::generate_key()).await.unwrap();
+        for id in ["a", "c", "b"] {
+            let mut entry = CachedMail::new(mail("inbox", id));
+            entry.body = Some(id.as_bytes().to_vec());
+            cache.put(&entry).await.unwrap();
+        }
+        cache
+            .put(&CachedMail::new(mail("spam", "d")))
+            .await
+            .unwrap();
+
+        let entry = cache
+            .get(&("inbox".into(), "b".into()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.body.as_deref(), Some(&b"b"[..]));
+        assert!(cache
+            .get(&("inbox".into(), "d".into()))
+            .await
+            .unwrap()
+            .is_none());
+
+        let listed = cache.list("inbox").await.unwrap();
+        let ids: Vec<_> = listed.iter().map(|e| e.mail.id.1.as_str()).collect();
+        assert_eq!(ids, ["c", "b", "a"]);
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove() {
+        let dir = temp_dir();
+        let cache = MailCache::open(&dir, crypto::generate_key()).await.unwrap();
+        let id = ("inbox".to_string(), "a".to_string());
+        cache.remove(&id).await.unwrap();
+
+        cache
+            .put(&CachedMail::new(mail("inbox", "a")))
+            .await
+            .unwrap();
+        cache.remove(&id).await.unwrap();
+        assert!(cache.get(&id).await.unwrap().is_none());
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_index_with_wrong_key() {
+        let dir = temp_dir();
+        let cache = MailCache::open(&dir, crypto::generate_key()).await.unwrap();
+        cache
+            .save_index(&CacheIndex {
+                user_id: "user".into(),
+                inboxes: vec!["inbox".into()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(cache.load_index().await.unwrap().inboxes, ["inbox"]);
+
+        let cache = MailCache::open(&dir, crypto::generate_key()).await.unwrap();
+        assert!(matches!(cache.load_index().await, Err(Error::InvalidMac)));
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -1,27 +1,63 @@
 use super::config;
 use super::*;
 use crate::api::{
-    bucket_permission, group, mail, mailbody, mailbox, mailboxgrouproot, mailfolder, permission,
-    salt, session, user,
+    apply_label, blob, bucket_permission, delete_mail, entity_event_batch, file, group, mail,
+    mail_details, mail_details_draft, mailbody, mailbox, mailboxgrouproot, mailfolder, mailheaders,
+    manage_label, move_mail, permission, salt, second_factor, session, unread_mail_state, user,
 };
+use crate::cache::{CacheIndex, CachedMail, MailCache};
 use crate::{crypto, http_client::HttpClient};
-use anyhow::{anyhow, bail, Context, Result};
+use lz4_flex::block::DecompressError;
 use lz4_flex::decompress_into;
-use tracing::debug;
+use reqwest::StatusCode;
+use std::time::Duration;
+use tracing::{debug, warn};
 use types::{
-    Aes128Key, Base64, BucketPermission, BucketPermissionType, GroupType, Id, Mail, MailFolderType,
-    Permission, PermissionType, ReadStatus, User,
+    Aes128Key, ArchiveDataType, Attachment, Base64, BatchResult, BucketPermission,
+    BucketPermissionType, ChallengeType, EncryptedHeaders, EntityEvent, EntityUpdate, Folder,
+    GroupType, Headers, Id, IdTuple, Label, Mail, MailChange, MailDetails, MailFolder,
+    MailFolderType, OperationType, Permission, PermissionType, ReadStatus, SavedSession, Sender,
+    Session, SyncState, User, WebSocketMessage,
 };
-use websocket::WebSocketConnector;
+use websocket::{Backoff, WebSocketConnector};
 
-use async_stream::try_stream;
+use async_stream::{stream, try_stream};
 use futures_core::stream::Stream;
+use futures_util::{pin_mut, StreamExt};
+use rsa::RsaPrivateKey;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
+
+const SECOND_FACTOR_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Time to wait for a second factor before the login fails.
+const SECOND_FACTOR_TIMEOUT: Duration = Duration::from_secs(120);
+/// Connections which stayed open this long reset the reconnect backoff, even without messages.
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+/// Upper bound for buffers sized by the server, the buffer still grows beyond it if needed.
+const MAX_PREALLOCATION: usize = 16 * 1024 * 1024;
+/// A byte of lz4 compressed data expands to at most this many bytes.
+const LZ4_MAX_RATIO: usize = 255;
 
 pub struct Client {
     config: config::Account,
     client: HttpClient,
     inboxes: Vec<String>,
+    folders: Vec<MailFolder>,
+    labels: Vec<Label>,
+    mail_group: Id,
+    /// List id of the folders and labels of the mailbox.
+    folder_list: Id,
     user: User,
+    access_key: Aes128Key,
+    cache: Option<MailCache>,
+    offline: bool,
+    /// Resolved bucket keys by bucket permission list, shared by all mails of the bucket.
+    bucket_keys: Mutex<HashMap<Id, Aes128Key>>,
+    /// Decrypted private keys by group.
+    private_keys: Mutex<HashMap<Id, RsaPrivateKey>>,
 }
 
 #[derive(Debug)]
@@ -30,82 +66,583 @@ pub struct MailContent {
     pub name: Option<String>,
     pub address: String,
     pub body: Option<String>,
+    pub headers: Option<Headers>,
+    pub first_recipient: Participant,
+    pub to_recipients: Vec<Participant>,
+    pub cc_recipients: Vec<Participant>,
+    pub bcc_recipients: Vec<Participant>,
+    pub reply_tos: Vec<Participant>,
+}
+
+/// Sender or recipient of a mail, the name is only decrypted if `show_name` is set.
+#[derive(Debug, Clone)]
+pub struct Participant {
+    pub name: Option<String>,
+    pub address: String,
 }
 
 struct SessionData {
     client: HttpClient,
-    user_passphrase_key: Aes128Key,
+    user_passphrase_key: crypto::PassphraseKey,
     user_id: Id,
+    access_key: Aes128Key,
 }
 
 impl Client {
     pub async fn new(config: &config::Account) -> Result<Client> {
+        Self::new_with_second_factor(config, || None).await
+    }
+
+    /// Like [`Client::new`], but asks `totp_code` for a TOTP code if the account requires a second
+    /// factor and `config.totp_secret` is not set.
+    ///
+    /// If no code is available the login fails with [`Error::Authentication`]. Accounts with only
+    /// other second factors (e.g. U2F) wait for the session to be approved from another client, up
+    /// to two minutes.
+    pub async fn new_with_second_factor<F>(config: &config::Account, totp_code: F) -> Result<Client>
+    where
+        F: FnOnce() -> Option<String>,
+    {
         let SessionData {
             client,
             user_passphrase_key,
             user_id,
-        } = Self::create_session(config).await?;
+            access_key,
+        } = Self::create_session(config, totp_code).await?;
         let mut user = user::fetch(&client, &user_id).await?;
-        user.unlock_group_keys(&user_passphrase_key);
+        user.unlock_group_keys(&user_passphrase_key)?;
+        let cache_key = crypto::derive_cache_key(&user_passphrase_key);
+        Self::from_user(config, client, user, access_key, Some(cache_key)).await
+    }
+
+    /// Creates a client which only reads from the mail cache in `config.cache_dir`.
+    ///
+    /// The cache key is derived from the password, so no request is sent to the server. Listing
+    /// and decrypting mails only works for mails which were cached before, everything else fails
+    /// with [`Error::Offline`].
+    pub async fn offline(config: &config::Account) -> Result<Client> {
+        let dir = config
+            .cache_dir
+            .as_ref()
+            .ok_or_else(|| Error::MissingData("No cache directory configured".into()))?;
+        let salt = MailCache::load_salt(dir).await?;
+        let user_passphrase_key =
+            crypto::create_user_passphrase_key(&config.password, &salt.salt, &salt.kdf_version)?;
+        let cache = MailCache::open(dir, crypto::derive_cache_key(&user_passphrase_key)).await?;
+        let index = cache.load_index().await?;
+
+        Ok(Client {
+            config: config.clone(),
+            client: HttpClient::new(config.endpoint.clone()),
+            inboxes: index.inboxes,
+            folders: Vec::new(),
+            labels: Vec::new(),
+            mail_group: Id::new(),
+            folder_list: Id::new(),
+            user: User::offline(index.user_id),
+            access_key: crypto::generate_key(),
+            cache: Some(cache),
+            offline: true,
+            bucket_keys: Default::default(),
+            private_keys: Default::default(),
+        })
+    }
+
+    /// Resumes a session previously exported with [`Client::save_session`].
+    ///
+    /// Falls back to a full login if the saved access token is no longer valid.
+    pub async fn resume(config: &config::Account, saved: &SavedSession) -> Result<Client> {
+        Self::resume_with_second_factor(config, saved, || None).await
+    }
+
+    /// Like [`Client::resume`], but passes `totp_code` to
+    /// [`Client::new_with_second_factor`] if it has to log in again.
+    pub async fn resume_with_second_factor<F>(
+        config: &config::Account,
+        saved: &SavedSession,
+        totp_code: F,
+    ) -> Result<Client>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let mut client = HttpClient::new(config.endpoint.clone());
+        client.set_access_token(saved.access_token.clone());
+        let access_key = match session::fetch_access_key(&client).await {
+            Ok(Some(access_key)) => access_key,
+            Ok(None) => {
+                warn!("Saved session has no access key, logging in again");
+                return Self::new_with_second_factor(config, totp_code).await;
+            }
+            Err(e) if Self::is_invalid_session(&e) => {
+                warn!("Saved session is no longer valid, logging in again");
+                return Self::new_with_second_factor(config, totp_code).await;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut user = user::fetch(&client, &saved.user_id).await?;
+        user.import_group_keys(&access_key, &saved.group_keys);
+        let cache_key = saved
+            .cache_key
+            .map(|key| crypto::decrypt_key(&access_key, &key));
+        Self::from_user(config, client, user, access_key, cache_key).await
+    }
+
+    /// Exports the current session so it can be resumed with [`Client::resume`].
+    pub fn save_session(&self) -> Result<SavedSession> {
+        Ok(SavedSession {
+            access_token: self
+                .client
+                .get_access_token()
+                .ok_or(Error::NotAuthenticated)?
+                .clone(),
+            user_id: self.user.id.clone(),
+            group_keys: self.user.export_group_keys(&self.access_key),
+            cache_key: self
+                .cache
+                .as_ref()
+                .map(|cache| crypto::encrypt_key(&self.access_key, cache.key())),
+        })
+    }
+
+    /// Deletes the current session on the server.
+    ///
+    /// Afterwards every call that needs authentication fails with [`Error::NotAuthenticated`].
+    pub async fn logout(&mut self) -> Result<()> {
+        session::delete(&self.client).await?;
+        self.client.clear_access_token();
+        Ok(())
+    }
+
+    fn is_invalid_session(e: &Error) -> bool {
+        matches!(
+            e,
+            Error::SessionExpired | Error::Status(StatusCode::NOT_FOUND)
+        )
+    }
+
+    async fn from_user(
+        config: &config::Account,
+        client: HttpClient,
+        user: User,
+        access_key: Aes128Key,
+        cache_key: Option<Aes128Key>,
+    ) -> Result<Client> {
         // let user_group_info = group_info::fetch(&access_token, &user.user_group.group_info)?;
         let mail_member = user
             .memberships
             .iter()
             .find(|membership| membership.group_type == GroupType::Mail)
-            .context("Could not find group with type mail")?;
+            .ok_or_else(|| Error::MissingData("Could not find group with type mail".into()))?;
 
-        let root = mailboxgrouproot::fetch(&client, &mail_member.group).await?;
-        let mailbox = mailbox::fetch(&client, &root).await?;
-        let folders = mailfolder::fetch(&client, &mailbox).await?;
+        let mail_group = mail_member.group.clone();
+        let root = mailboxgrouproot::fetch(&client, &mail_group).await?;
+        let folder_list = mailbox::fetch(&client, &root).await?;
+        let (folders, labels) = Self::fetch_folders(&client, &user, &folder_list).await?;
 
-        let inboxes: Vec<_> = folders
-            .into_iter()
-            .filter(|folder| {
-                folder.folder_type == MailFolderType::Inbox
-                    || (config.watch_spam && folder.folder_type == MailFolderType::Spam)
-            })
-            .map(|folder| folder.mails)
-            .collect();
+        let inboxes: Vec<_> = if config.watch_folders.is_empty() {
+            folders
+                .iter()
+                .filter(|folder| {
+                    folder.folder_type == MailFolderType::Inbox
+                        || (config.watch_spam && folder.folder_type == MailFolderType::Spam)
+                })
+                .map(|folder| folder.mails.clone())
+                .collect()
+        } else {
+            let paths: Vec<_> = folders
+                .iter()
+                .map(|folder| Self::path_of(&folders, folder))
+                .collect();
+            for path in &config.watch_folders {
+                if !paths.contains(&path.trim_matches('/').to_string()) {
+                    warn!("Watched folder {} does not exist", path);
+                }
+            }
+            folders
+                .iter()
+                .zip(paths)
+                .filter(|(_, path)| {
+                    config
+                        .watch_folders
+                        .iter()
+                        .any(|watched| watched.trim_matches('/') == path)
+                })
+                .map(|(folder, _)| folder.mails.clone())
+                .collect()
+        };
+
+        let cache = match (&config.cache_dir, cache_key) {
+            (Some(dir), Some(key)) => {
+                let cache = MailCache::open(dir, key).await?;
+                cache
+                    .save_index(&CacheIndex {
+                        user_id: user.id.clone(),
+                        inboxes: inboxes.clone(),
+                    })
+                    .await?;
+                Some(cache)
+            }
+            (Some(_), None) => {
+                warn!("Saved session has no cache key, mail cache is disabled");
+                None
+            }
+            _ => None,
+        };
 
         Ok(Client {
             config: config.clone(),
             client,
             inboxes,
+            folders,
+            labels,
+            mail_group,
+            folder_list,
             user,
+            access_key,
+            cache,
+            offline: false,
+            bucket_keys: Default::default(),
+            private_keys: Default::default(),
         })
     }
 
-    async fn create_session(config: &config::Account) -> Result<SessionData> {
-        let mut client = HttpClient::new();
+    async fn fetch_folders(
+        client: &HttpClient,
+        user: &User,
+        folder_list: &Id,
+    ) -> Result<(Vec<MailFolder>, Vec<Label>)> {
+        let mut folders = Vec::new();
+        let mut labels = Vec::new();
+        for folder in mailfolder::fetch(client, folder_list).await? {
+            let id = folder.id.clone();
+            let decrypted = if folder.folder_type == MailFolderType::Label {
+                Self::decrypt_label(user, folder).map(|label| labels.push(label))
+            } else {
+                Self::decrypt_folder(user, folder).map(|folder| folders.push(folder))
+            };
+            if let Err(e) = decrypted {
+                warn!("Skipping folder {:?}, could not decrypt it: {}", id, e);
+            }
+        }
+        Ok((folders, labels))
+    }
+
+    fn folder_session_key(user: &User, folder: &Folder) -> Result<Aes128Key> {
+        let group_key = user.get_group_key(&folder.owner_group).ok_or_else(|| {
+            Error::MissingData(format!("No group key for group {}", folder.owner_group))
+        })?;
+        Ok(crypto::decrypt_key(
+            &group_key,
+            &folder.owner_enc_session_key,
+        ))
+    }
+
+    fn decrypt_label(user: &User, folder: Folder) -> Result<Label> {
+        let session_key = Self::folder_session_key(user, &folder)?;
+        let color = match &folder.color {
+            Some(color) => crypto::decrypt_string(&session_key, color)?,
+            None => String::new(),
+        };
+        Ok(Label {
+            name: crypto::decrypt_string(&session_key, &folder.name)?,
+            id: folder.id,
+            color,
+        })
+    }
+
+    /// System folders which can't be decrypted fall back to their default name.
+    fn decrypt_folder(user: &User, folder: Folder) -> Result<MailFolder> {
+        let name = Self::folder_session_key(user, &folder)
+            .and_then(|session_key| crypto::decrypt_string(&session_key, &folder.name));
+        let name = match name {
+            Ok(name) if !name.is_empty() => name,
+            Ok(_) => folder.folder_type.default_name().to_string(),
+            Err(e) if folder.folder_type != MailFolderType::Custom => {
+                warn!("Could not decrypt name of folder {:?}: {}", folder.id, e);
+                folder.folder_type.default_name().to_string()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(MailFolder {
+            name,
+            id: folder.id,
+            folder_type: folder.folder_type,
+            mails: folder.mails,
+            parent: folder.parent_folder,
+        })
+    }
+
+    /// Returns the labels of the mailbox.
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// Creates a new label and returns it.
+    pub async fn create_label(&mut self, name: &str, color: &str) -> Result<Label> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let session_key = crypto::generate_key();
+        let owner_enc_session_key =
+            crypto::encrypt_key(&self.group_key(&self.mail_group)?, &session_key);
+        manage_label::create(
+            &self.client,
+            &self.mail_group,
+            owner_enc_session_key,
+            &crypto::aes_encrypt(&session_key, name.as_bytes())?,
+            &crypto::aes_encrypt(&session_key, color.as_bytes())?,
+        )
+        .await?;
+
+        let known: Vec<_> = self.labels.iter().map(|label| label.id.clone()).collect();
+        self.refresh_folders().await?;
+        self.labels
+            .iter()
+            .find(|label| !known.contains(&label.id) && label.name == name)
+            .cloned()
+            .ok_or_else(|| Error::MissingData("Created label not found".into()))
+    }
+
+    /// Renames and recolors `label`.
+    pub async fn update_label(&mut self, label: &Label, name: &str, color: &str) -> Result<()> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let folders = mailfolder::fetch(&self.client, &self.folder_list).await?;
+        let folder = folders
+            .iter()
+            .find(|folder| folder.id == label.id)
+            .ok_or_else(|| Error::MissingData(format!("Label {:?} not found", label.id)))?;
+        let session_key = Self::folder_session_key(&self.user, folder)?;
+        mailfolder::update_label(
+            &self.client,
+            &label.id,
+            &crypto::aes_encrypt(&session_key, name.as_bytes())?,
+            &crypto::aes_encrypt(&session_key, color.as_bytes())?,
+        )
+        .await?;
+        self.refresh_folders().await
+    }
+
+    /// Deletes `label`, mails keep existing without it.
+    pub async fn delete_label(&mut self, label: &Label) -> Result<()> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        manage_label::delete(&self.client, &label.id).await?;
+        self.labels.retain(|l| l.id != label.id);
+        Ok(())
+    }
+
+    /// Adds `labels` to `mails`, see [`Client::move_mails`] for how failures are reported.
+    pub async fn apply_labels(&self, mails: &[IdTuple], labels: &[Label]) -> Result<BatchResult> {
+        let labels: Vec<_> = labels.iter().map(|label| label.id.clone()).collect();
+        self.run_batched(mails, |batch| {
+            let labels = &labels;
+            async move { apply_label::send(&self.client, &batch, labels, &[]).await }
+        })
+        .await
+    }
+
+    /// Removes `labels` from `mails`, see [`Client::move_mails`] for how failures are reported.
+    pub async fn remove_labels(&self, mails: &[IdTuple], labels: &[Label]) -> Result<BatchResult> {
+        let labels: Vec<_> = labels.iter().map(|label| label.id.clone()).collect();
+        self.run_batched(mails, |batch| {
+            let labels = &labels;
+            async move { apply_label::send(&self.client, &batch, &[], labels).await }
+        })
+        .await
+    }
+
+    /// Fetches the folders and labels again, e.g. after they were changed.
+    pub async fn refresh_folders(&mut self) -> Result<()> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let (folders, labels) =
+            Self::fetch_folders(&self.client, &self.user, &self.folder_list).await?;
+        self.folders = folders;
+        self.labels = labels;
+        Ok(())
+    }
+
+    /// Returns the path of `folder` with the names of all parent folders, e.g. `Alerts/Prod`.
+    pub fn folder_path(&self, folder: &MailFolder) -> String {
+        Self::path_of(&self.folders, folder)
+    }
+
+    /// Returns the folder with the path `path`, see [`Client::folder_path`].
+    pub fn folder_by_path(&self, path: &str) -> Option<&MailFolder> {
+        let path = path.trim_matches('/');
+        self.folders
+            .iter()
+            .find(|folder| Self::path_of(&self.folders, folder) == path)
+    }
+
+    fn path_of(folders: &[MailFolder], folder: &MailFolder) -> String {
+        let mut names = vec![folder.name.as_str()];
+        let mut parent = folder.parent.as_ref();
+        // the depth is bounded in case the server sends a cycle
+        while let Some(id) = parent.filter(|_| names.len() <= folders.len()) {
+            let Some(parent_folder) = folders.iter().find(|f| f.id == *id) else {
+                break;
+            };
+            names.push(&parent_folder.name);
+            parent = parent_folder.parent.as_ref();
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    /// Returns every mail folder of the mailbox, including custom folders and subfolders.
+    ///
+    /// The folders are only known when online, an offline client returns no folders.
+    pub fn folders(&self) -> &[MailFolder] {
+        &self.folders
+    }
+
+    /// Watches the folders for which `filter` returns true instead of Inbox and Spam.
+    ///
+    /// ```ignore
+    /// client
+    ///     .watch_folders(|folder| {
+    ///         folder.folder_type == MailFolderType::Inbox || folder.name == "Alerts"
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn watch_folders<F>(&mut self, filter: F) -> Result<()>
+    where
+        F: Fn(&MailFolder) -> bool,
+    {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        self.inboxes = self
+            .folders
+            .iter()
+            .filter(|folder| filter(folder))
+            .map(|folder| folder.mails.clone())
+            .collect();
+        if let Some(cache) = &self.cache {
+            cache
+                .save_index(&CacheIndex {
+                    user_id: self.user.id.clone(),
+                    inboxes: self.inboxes.clone(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn create_session<F>(config: &config::Account, totp_code: F) -> Result<SessionData>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let mut client = HttpClient::new(config.endpoint.clone());
         let salt = salt::fetch(&client, &config.email_address).await?;
-        let user_passphrase_key = crypto::create_user_passphrase_key(&config.password, &salt);
-        let session = session::fetch(&client, &config.email_address, &user_passphrase_key).await?;
+        if let Some(dir) = &config.cache_dir {
+            MailCache::save_salt(dir, &salt).await?;
+        }
+        let user_passphrase_key =
+            crypto::create_user_passphrase_key(&config.password, &salt.salt, &salt.kdf_version)?;
+        let access_key = crypto::generate_key();
+        let session = session::fetch(
+            &client,
+            &config.email_address,
+            user_passphrase_key.as_bytes(),
+            Some(&access_key),
+        )
+        .await?;
+        if !session.challenges.is_empty() {
+            Self::complete_second_factor(&client, config, &session, totp_code).await?;
+        }
         client.set_access_token(session.access_token);
         Ok(SessionData {
             client,
             user_passphrase_key,
             user_id: session.user,
+            access_key,
         })
     }
 
+    async fn complete_second_factor<F>(
+        client: &HttpClient,
+        config: &config::Account,
+        session: &Session,
+        totp_code: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        debug!("Session requires a second factor");
+        if session
+            .challenges
+            .iter()
+            .any(|c| c.challenge_type == ChallengeType::Otp)
+        {
+            let code = match &config.totp_secret {
+                Some(secret) => Some(crypto::totp_code(secret)?),
+                None => totp_code(),
+            };
+            let Some(code) = code else {
+                warn!("Account requires a TOTP code, but none is available");
+                return Err(Error::Authentication);
+            };
+            let session_id = session::session_id(&session.access_token)?;
+            second_factor::send_totp(client, &session_id, &code).await?;
+        }
+
+        let deadline = tokio::time::Instant::now() + SECOND_FACTOR_TIMEOUT;
+        while second_factor::fetch_pending(client, &session.access_token).await? {
+            if tokio::time::Instant::now() >= deadline {
+                warn!("Second factor was not completed in time");
+                return Err(Error::Authentication);
+            }
+            debug!("Waiting for second factor");
+            tokio::time::sleep(SECOND_FACTOR_POLL_INTERVAL).await;
+        }
+        debug!("Second factor completed");
+        Ok(())
+    }
+
+    /// Returns the mails of the watched folders, newest first.
+    ///
+    /// In offline mode only the cached mails are returned.
     pub fn get_mails(&self) -> impl Stream<Item = Result<Mail>> + '_ {
         try_stream! {
+            if let (true, Some(cache)) = (self.offline, &self.cache) {
+                for inbox in &self.inboxes {
+                    for entry in cache.list(inbox).await? {
+                        yield entry.mail;
+                    }
+                }
+                return;
+            }
+
             for inbox in &self.inboxes {
                 let mut start = None;
-                let curr_mails = mail::fetch_from_inbox(&self.client, &inbox, start).await?;
+                let curr_mails = mail::fetch_from_inbox(&self.client, inbox, start).await?;
                 let mut n = curr_mails.len();
                 let mut last = curr_mails.last().map_or("".into(), |m| m.id.1.clone());
 
                 for mail in curr_mails {
+                    self.cache_mail(&mail).await;
                     yield mail
                 }
 
                 while n > 0 {
                     start = Some(last);
-                    let curr_mails = mail::fetch_from_inbox(&self.client, &inbox, start).await?;
+                    let curr_mails = mail::fetch_from_inbox(&self.client, inbox, start).await?;
                     last = curr_mails.last().map_or("".into(), |m| m.id.1.clone());
                     n = curr_mails.len();
                     for mail in curr_mails {
+                        self.cache_mail(&mail).await;
                         yield mail
                     }
                 }
@@ -113,46 +650,93 @@ impl Client {
         }
     }
 
-    fn resolve_session_key_owner(&self, mail: &Mail) -> Result<Aes128Key> {
-        debug!("resolve session key with owner key");
-        let gk = self
-            .user
-            .get_group_key(&mail.owner_group)
-            .context("No group key for mail")?;
+    fn group_key(&self, group: &Id) -> Result<Aes128Key> {
+        self.user
+            .get_group_key(group)
+            .ok_or_else(|| Error::MissingData(format!("No group key for group {}", group)))
+    }
+
+    pub async fn get_mail(&self, id: &IdTuple) -> Result<Mail> {
+        if self.offline {
+            return self
+                .cached(id)
+                .await
+                .map(|entry| entry.mail)
+                .ok_or(Error::Offline);
+        }
+        let mail = mail::fetch_from_id(&self.client, &id.0, &id.1).await?;
+        self.cache_mail(&mail).await;
+        Ok(mail)
+    }
 
-        let key = mail
-            .owner_enc_session_key
-            .context("No owner enc session key for mail")?;
-        return Ok(crypto::decrypt_key(&gk, &key));
+    async fn cached(&self, id: &IdTuple) -> Option<CachedMail> {
+        let cache = self.cache.as_ref()?;
+        match cache.get(id).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Could not read mail {:?} from cache: {}", id, e);
+                None
+            }
+        }
     }
 
-    fn try_symmetric_permission(&self, perms: &Vec<Permission>) -> Option<Aes128Key> {
-        debug!("try symmetric permission");
-        let sym_perm = perms.iter().find(|p| {
-            p.permission_type == PermissionType::PublicSymmetric
-                || p.permission_type == PermissionType::Symmetric
-                    && p.owner_group
-                        .as_ref()
-                        .is_some_and(|g| self.user.has_group(&g))
-                    && p.owner_enc_session_key.is_some()
-        });
+    /// Stores `entry` in the cache, a failing cache never fails the request itself.
+    async fn store(&self, entry: &CachedMail) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(entry).await {
+                warn!("Could not write mail {:?} to cache: {}", entry.mail.id, e);
+            }
+        }
+    }
 
-        if let Some(sym) = sym_perm {
-            let gk = self
-                .user
-                .get_group_key(&sym.owner_group.as_ref().unwrap())
-                .unwrap();
-            let sk = sym.owner_enc_session_key.unwrap();
-            Some(crypto::decrypt_key(&gk, &sk))
-        } else {
-            None
+    /// Updates the cached metadata of `mail`, keeping its cached session key, body and details.
+    async fn cache_mail(&self, mail: &Mail) {
+        if self.cache.is_none() {
+            return;
         }
+        let mut entry = self
+            .cached(&mail.id)
+            .await
+            .unwrap_or_else(|| CachedMail::new(mail.clone()));
+        entry.mail = mail.clone();
+        self.store(&entry).await;
     }
 
-    async fn resolve_session_key_public_external(
-        &self,
-        perms: &Vec<Permission>,
-    ) -> Result<Aes128Key> {
+    async fn uncache_mail(&self, id: &IdTuple) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.remove(id).await {
+                warn!("Could not remove mail {:?} from cache: {}", id, e);
+            }
+        }
+    }
+
+    /// Returns whether `id` belongs to a mail in one of the watched folders.
+    pub fn is_watched(&self, id: &IdTuple) -> bool {
+        self.inboxes.contains(&id.0)
+    }
+
+    fn resolve_session_key_owner(&self, owner_group: &Id, key: &Aes128Key) -> Result<Aes128Key> {
+        debug!("resolve session key with owner key");
+        let gk = self.group_key(owner_group)?;
+        Ok(crypto::decrypt_key(&gk, key))
+    }
+
+    fn try_symmetric_permission(&self, perms: &[Permission]) -> Option<Aes128Key> {
+        debug!("try symmetric permission");
+        perms
+            .iter()
+            .filter(|p| {
+                p.permission_type == PermissionType::PublicSymmetric
+                    || p.permission_type == PermissionType::Symmetric
+            })
+            .find_map(|p| {
+                let gk = self.user.get_group_key(p.owner_group.as_ref()?)?;
+                let sk = p.owner_enc_session_key?;
+                Some(crypto::decrypt_key(&gk, &sk))
+            })
+    }
+
+    async fn resolve_session_key_public_external(&self, perms: &[Permission]) -> Result<Aes128Key> {
         debug!("resolve session key from public or external bucket");
         let pub_or_external_perm = perms
             .iter()
@@ -160,79 +744,90 @@ impl Client {
                 p.permission_type == PermissionType::Public
                     || p.permission_type == PermissionType::External
             })
-            .context("could not find public or external permission")?;
+            .ok_or_else(|| {
+                Error::MissingData("could not find public or external permission".into())
+            })?;
 
         let bucket_perm_id = &pub_or_external_perm
             .bucket
-            .clone()
-            .context("Bucket is null")?
+            .as_ref()
+            .ok_or_else(|| Error::MissingData("Bucket is null".into()))?
             .bucket_permissions;
-        let bucket_permissions = bucket_permission::fetch(&self.client, &bucket_perm_id).await?;
+        let bucket_enc_session_key = pub_or_external_perm
+            .bucket_enc_session_key
+            .ok_or_else(|| Error::MissingData("BucketEncSessionKey is not defined".into()))?;
+
+        let cached = self
+            .bucket_keys
+            .lock()
+            .unwrap()
+            .get(bucket_perm_id)
+            .copied();
+        let bucket_key = match cached {
+            Some(bucket_key) => bucket_key,
+            None => {
+                let bucket_key = self.resolve_bucket_key(bucket_perm_id).await?;
+                self.bucket_keys
+                    .lock()
+                    .unwrap()
+                    .insert(bucket_perm_id.clone(), bucket_key);
+                bucket_key
+            }
+        };
+        Ok(crypto::decrypt_key(&bucket_key, &bucket_enc_session_key))
+    }
+
+    async fn resolve_bucket_key(&self, bucket_perm_id: &Id) -> Result<Aes128Key> {
+        let bucket_permissions = bucket_permission::fetch(&self.client, bucket_perm_id).await?;
         let bucket_permission = bucket_permissions
             .iter()
             .find(|p| {
                 p.permission_type == BucketPermissionType::Public
                     || p.permission_type == BucketPermissionType::External
             })
-            .context("could not find public or external permission")?;
+            .ok_or_else(|| {
+                Error::MissingData("could not find public or external permission".into())
+            })?;
 
         match bucket_permission.permission_type {
-            BucketPermissionType::External => {
-                self.resolve_external_bucket(&bucket_permission, &pub_or_external_perm)
-            }
-            BucketPermissionType::Public => {
-                self.resolve_public_bucket(&bucket_permission, &pub_or_external_perm)
-                    .await
-            }
+            BucketPermissionType::External => self.resolve_external_bucket(bucket_permission),
+            BucketPermissionType::Public => self.resolve_public_bucket(bucket_permission).await,
         }
     }
 
-    fn resolve_external_bucket(
-        &self,
-        bucket_perm: &BucketPermission,
-        perm: &Permission,
-    ) -> Result<Aes128Key> {
+    fn resolve_external_bucket(&self, bucket_perm: &BucketPermission) -> Result<Aes128Key> {
         debug!("decrypt with external bucket");
         let bucket_key;
         if let Some(bk) = bucket_perm.owner_enc_bucket_key {
-            bucket_key = crypto::decrypt_key(
-                &self
-                    .user
-                    .get_group_key(&bucket_perm.owner_group.as_ref().unwrap())
-                    .unwrap(),
-                &bk,
-            );
+            let owner_group = bucket_perm
+                .owner_group
+                .as_ref()
+                .ok_or_else(|| Error::MissingData("Bucket permission has no owner".into()))?;
+            bucket_key = crypto::decrypt_key(&self.group_key(owner_group)?, &bk);
         } else if let Some(sym) = bucket_perm.sym_enc_bucket_key {
-            bucket_key = crypto::decrypt_key(&self.user.get_user_group_key(), &sym);
+            let user_group_key = self
+                .user
+                .get_user_group_key()
+                .ok_or_else(|| Error::MissingData("No user group key".into()))?;
+            bucket_key = crypto::decrypt_key(&user_group_key, &sym);
         } else {
-            bail!("BucketEncSessionKey is not defined for Permission")
+            return Err(Error::MissingData(
+                "BucketEncSessionKey is not defined for Permission".into(),
+            ));
         }
-
-        let msg = perm
-            .bucket_enc_session_key
-            .context("bucket enc session key not defined")?;
-        Ok(crypto::decrypt_key(&bucket_key, &msg))
+        Ok(bucket_key)
     }
 
-    async fn resolve_public_bucket(
-        &self,
-        bucket_perm: &BucketPermission,
-        perm: &Permission,
-    ) -> Result<Aes128Key> {
+    async fn resolve_public_bucket(&self, bucket_perm: &BucketPermission) -> Result<Aes128Key> {
         debug!("decrypt with public bucket");
         let pub_enc_bucket_key = bucket_perm
             .pub_enc_bucket_key
             .clone()
-            .context("PubEncBucketKey is not defined")?;
-
-        let bucket_enc_session_key = perm
-            .bucket_enc_session_key
-            .context("BucktEncSessionKey is not defined")?;
+            .ok_or_else(|| Error::MissingData("PubEncBucketKey is not defined".into()))?;
 
         let bucket_key = self
             .decrypt_bucket_key_key_pair_group(&bucket_perm.group, &pub_enc_bucket_key)
             .await?;
-        let sk = crypto::decrypt_key(&bucket_key, &bucket_enc_session_key);
 
         // if let Some(og) = &bucket_perm.owner_group {
         //     // update sym perm
@@ -241,7 +836,7 @@ impl Client {
         //     self.update_sym_perm_key(mail, perm, &bucket_perm_ogk, &bucket_perm_gk)?;
         // }
 
-        Ok(sk)
+        Ok(bucket_key)
     }
 
     // broken and should never be used
@@ -274,91 +869,1012 @@ impl Client {
         pub_enc_bucket_key: &Base64,
     ) -> Result<Aes128Key> {
         debug!("decrypt bucket key with key pair of group");
-        let group = group::fetch(&self.client, &key_pair).await?;
-        let key_pair = &group.keys[0];
-        let priv_key = crypto::decrypt_rsa_key(
-            &self.user.get_group_key(&group.id).unwrap(),
-            &key_pair.sym_enc_priv_key,
-        )?;
-
+        let priv_key = self.private_key(key_pair).await?;
         crypto::rsa_decrypt(&priv_key, pub_enc_bucket_key)?
             .try_into()
-            .map_err(|_| anyhow!("Could not convert to [u8; 16]"))
+            .map_err(|_| Error::InvalidData("Could not convert to [u8; 16]".into()))
+    }
+
+    async fn private_key(&self, group: &Id) -> Result<RsaPrivateKey> {
+        if let Some(priv_key) = self.private_keys.lock().unwrap().get(group) {
+            return Ok(priv_key.clone());
+        }
+
+        let group = group::fetch(&self.client, group).await?;
+        let key_pair = group
+            .keys
+            .first()
+            .ok_or_else(|| Error::MissingData(format!("Group {} has no key pair", group.id)))?;
+        let priv_key =
+            crypto::decrypt_rsa_key(&self.group_key(&group.id)?, &key_pair.sym_enc_priv_key)?;
+        self.private_keys
+            .lock()
+            .unwrap()
+            .insert(group.id.clone(), priv_key.clone());
+        Ok(priv_key)
     }
 
     async fn resolve_session_key(&self, mail: &Mail) -> Result<Aes128Key> {
+        self.resolve_instance_session_key(
+            mail.owner_enc_session_key.as_ref(),
+            &mail.owner_group,
+            &mail.permissions,
+        )
+        .await
+    }
+
+    /// Resolves the session key of any encrypted entity, e.g. a mail or a file.
+    async fn resolve_instance_session_key(
+        &self,
+        owner_enc_session_key: Option<&Aes128Key>,
+        owner_group: &Id,
+        permissions: &Id,
+    ) -> Result<Aes128Key> {
         debug!("Resolve session key");
-        if mail.owner_enc_session_key.is_some() && self.user.has_group(&mail.owner_group) {
-            self.resolve_session_key_owner(mail)
+        if let (Some(key), true) = (owner_enc_session_key, self.user.has_group(owner_group)) {
+            self.resolve_session_key_owner(owner_group, key)
         } else {
-            let perms = permission::fetch(&self.client, &mail.permissions).await?;
-            Ok(self
-                .try_symmetric_permission(&perms)
-                .unwrap_or(self.resolve_session_key_public_external(&perms).await?))
+            let perms = permission::fetch(&self.client, permissions).await?;
+            match self.try_symmetric_permission(&perms) {
+                Some(key) => Ok(key),
+                None => self.resolve_session_key_public_external(&perms).await,
+            }
         }
     }
 
+    /// Decrypts subject, body, headers and the names of sender and recipients of `mail`,
+    /// depending on the config.
+    ///
+    /// Works for mails with a legacy mail body as well as for mails with mail details. The
+    /// session key and the encrypted body are cached, so the mail can be read offline later.
     pub async fn decrypt(&self, mail: &Mail) -> Result<MailContent> {
-        let session_key = self.resolve_session_key(mail).await?;
+        let mut entry = match self.cached(&mail.id).await {
+            Some(entry) => entry,
+            None if self.offline => return Err(Error::Offline),
+            None => CachedMail::new(mail.clone()),
+        };
+        let mut changed = false;
+
+        let session_key = match entry.session_key {
+            Some(session_key) => session_key,
+            None if self.offline => return Err(Error::Offline),
+            None => {
+                let session_key = self.resolve_session_key(mail).await?;
+                entry.session_key = Some(session_key);
+                changed = true;
+                session_key
+            }
+        };
 
         let subject = if self.config.show_subject {
-            let tmp = crypto::aes_decrypt(&session_key, &mail.subject)?;
-            Some(
-                std::str::from_utf8(&tmp)
-                    .expect("Subject could not converted to UTF-8")
-                    .to_string(),
-            )
+            Some(crypto::decrypt_string(&session_key, &mail.subject)?)
         } else {
             None
         };
 
         let name = if self.config.show_name {
-            let tmp = crypto::aes_decrypt(&session_key, &mail.sender.name)?;
-            Some(
-                std::str::from_utf8(&tmp)
-                    .expect("Name could not converted to UTF-8")
-                    .to_string(),
-            )
+            Some(crypto::decrypt_string(&session_key, &mail.sender.name)?)
         } else {
             None
         };
 
         let address = mail.sender.address.to_string();
 
-        let body = if self.config.show_body {
-            let mailbody = mailbody::fetch(&self.client, &mail.body).await?;
-            let compressed_text = crypto::aes_decrypt(&session_key, &mailbody)?;
-            let mut buf: Vec<u8> = vec![0; mailbody.len() * 6];
-            let size = decompress_into(&compressed_text, &mut buf)?;
-            buf.resize(size, 0);
-            Some(
-                std::str::from_utf8(&buf)
-                    .expect("Body could not be converted to UTF-8")
-                    .to_string(),
-            )
+        // newer mails and drafts keep their body, recipients and headers in the details
+        if mail.mail_details.is_some() || mail.mail_details_draft.is_some() {
+            changed |= self.load_details(mail, &mut entry).await?;
+        }
+
+        let body = if !self.config.show_body {
+            None
+        } else if let Some(details) = &entry.details {
+            Some(Self::decrypt_details_body(&session_key, details)?)
+        } else {
+            if entry.body.is_none() {
+                let body = mail.body.as_ref().ok_or_else(|| {
+                    Error::MissingData("Mail has neither body nor details".into())
+                })?;
+                if self.offline {
+                    return Err(Error::Offline);
+                }
+                entry.body = Some(mailbody::fetch(&self.client, body).await?);
+                changed = true;
+            }
+            let mailbody = entry.body.as_deref().unwrap_or_default();
+            let compressed_text = crypto::aes_decrypt(&session_key, mailbody)?;
+            Some(Self::decompress(&compressed_text)?)
+        };
+
+        let headers = if self.config.show_headers {
+            changed |= self.load_headers(mail, &mut entry).await?;
+            let headers = match &entry.details {
+                Some(details) => details.headers.as_ref(),
+                None => entry.headers.as_ref(),
+            };
+            match headers {
+                Some(headers) => Some(Self::decrypt_headers(&session_key, headers)?),
+                None => None,
+            }
         } else {
             None
         };
 
+        let participants = |senders: &[Sender]| {
+            senders
+                .iter()
+                .map(|s| self.decrypt_participant(&session_key, &s.name, &s.address))
+                .collect::<Result<Vec<_>>>()
+        };
+        let first_recipient = self.decrypt_participant(
+            &session_key,
+            &mail.first_recipient.name,
+            &mail.first_recipient.address,
+        )?;
+        let (to_recipients, cc_recipients, bcc_recipients, reply_tos) = match &entry.details {
+            Some(details) => (
+                participants(&details.recipients.to_recipients)?,
+                participants(&details.recipients.cc_recipients)?,
+                participants(&details.recipients.bcc_recipients)?,
+                details
+                    .reply_tos
+                    .iter()
+                    .map(|r| self.decrypt_participant(&session_key, &r.name, &r.address))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            None => (
+                participants(&mail.to_recipients)?,
+                participants(&mail.cc_recipients)?,
+                participants(&mail.bcc_recipients)?,
+                participants(&mail.reply_tos)?,
+            ),
+        };
+
+        if changed && !self.offline {
+            entry.mail = mail.clone();
+            self.store(&entry).await;
+        }
+
         Ok(MailContent {
             subject,
             name,
             address,
             body,
+            headers,
+            first_recipient,
+            to_recipients,
+            cc_recipients,
+            bcc_recipients,
+            reply_tos,
+        })
+    }
+
+    fn decrypt_participant(
+        &self,
+        session_key: &Aes128Key,
+        name: &[u8],
+        address: &str,
+    ) -> Result<Participant> {
+        let name = if self.config.show_name {
+            Some(crypto::decrypt_string(session_key, name)?)
+        } else {
+            None
+        };
+        Ok(Participant {
+            name,
+            address: address.to_string(),
         })
     }
 
+    /// Makes sure `entry` holds the mail details of `mail` and returns whether they were fetched.
+    ///
+    /// Sent and received mails keep them in the blob store, drafts as a regular entity.
+    async fn load_details(&self, mail: &Mail, entry: &mut CachedMail) -> Result<bool> {
+        if entry.details.is_some() {
+            return Ok(false);
+        }
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let details = match (&mail.mail_details, &mail.mail_details_draft) {
+            (Some(id), _) => {
+                let access =
+                    blob::fetch_read_access(&self.client, ArchiveDataType::MailDetails, &id.0, id)
+                        .await?;
+                mail_details::fetch(&self.client, &access, id)
+                    .await?
+                    .details
+            }
+            (None, Some(id)) => mail_details_draft::fetch(&self.client, id).await?.details,
+            (None, None) => return Err(Error::MissingData("Mail has no mail details".into())),
+        };
+        entry.details = Some(details);
+        Ok(true)
+    }
+
+    /// Makes sure `entry` holds the headers of `mail` if it has any and returns whether they
+    /// were fetched.
+    async fn load_headers(&self, mail: &Mail, entry: &mut CachedMail) -> Result<bool> {
+        if mail.mail_details.is_some() || mail.mail_details_draft.is_some() {
+            return self.load_details(mail, entry).await;
+        }
+        let Some(id) = &mail.headers else {
+            return Ok(false);
+        };
+        if entry.headers.is_some() {
+            return Ok(false);
+        }
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        entry.headers = Some(mailheaders::fetch(&self.client, id).await?);
+        Ok(true)
+    }
+
+    fn decrypt_headers(session_key: &Aes128Key, headers: &EncryptedHeaders) -> Result<Headers> {
+        let raw = match (&headers.compressed_headers, &headers.headers) {
+            (Some(compressed_headers), _) => {
+                Self::decompress(&crypto::aes_decrypt(session_key, compressed_headers)?)?
+            }
+            (None, Some(headers)) => crypto::decrypt_string(session_key, headers)?,
+            (None, None) => String::new(),
+        };
+        Ok(Headers::parse(&raw))
+    }
+
+    fn decrypt_details_body(session_key: &Aes128Key, details: &MailDetails) -> Result<String> {
+        match (&details.body.compressed_text, &details.body.text) {
+            (Some(compressed_text), _) => {
+                Self::decompress(&crypto::aes_decrypt(session_key, compressed_text)?)
+            }
+            (None, Some(text)) => crypto::decrypt_string(session_key, text),
+            (None, None) => Ok(String::new()),
+        }
+    }
+
+    /// Decompresses lz4 compressed text, as used for bodies and headers.
+    ///
+    /// The output buffer starts at six times the input and grows up to the maximum lz4 ratio.
+    fn decompress(compressed: &[u8]) -> Result<String> {
+        let max_len = compressed.len().saturating_mul(LZ4_MAX_RATIO);
+        let mut len = compressed.len().saturating_mul(6);
+        loop {
+            let mut buf: Vec<u8> = vec![0; len];
+            match decompress_into(compressed, &mut buf) {
+                Ok(size) => {
+                    buf.truncate(size);
+                    return Ok(String::from_utf8_lossy(&buf).into_owned());
+                }
+                Err(DecompressError::OutputTooSmall { .. }) if len < max_len => {
+                    len = len.saturating_mul(2).min(max_len);
+                }
+                Err(e) => return Err(Error::InvalidData(e.to_string())),
+            }
+        }
+    }
+
+    /// Fetches the attachments of `mail` and decrypts their names, MIME types and sizes.
+    pub async fn attachments(&self, mail: &Mail) -> Result<Vec<Attachment>> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let mut attachments = Vec::with_capacity(mail.attachments.len());
+        for id in &mail.attachments {
+            let file = file::fetch(&self.client, id).await?;
+            let session_key = self
+                .resolve_instance_session_key(
+                    file.owner_enc_session_key.as_ref(),
+                    &file.owner_group,
+                    &file.permissions,
+                )
+                .await?;
+            let mime_type = match &file.mime_type {
+                Some(mime_type) => crypto::decrypt_string(&session_key, mime_type)?,
+                None => String::new(),
+            };
+            attachments.push(Attachment {
+                name: crypto::decrypt_string(&session_key, &file.name)?,
+                id: file.id,
+                mime_type,
+                size: file.size,
+                session_key,
+                blobs: file.blobs,
+            });
+        }
+        Ok(attachments)
+    }
+
+    /// Downloads and decrypts the data of `attachment` chunk by chunk.
+    ///
+    /// Every blob of the attachment is encrypted on its own, so each chunk is decrypted as soon
+    /// as it is downloaded.
+    pub fn attachment_stream<'a>(
+        &'a self,
+        attachment: &'a Attachment,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
+        try_stream! {
+            let mut access: Option<blob::BlobAccess> = None;
+            for b in &attachment.blobs {
+                let curr_access = match access.take() {
+                    Some(access) if access.archive_id == b.archive_id => access,
+                    _ => blob::fetch_read_access(
+                        &self.client,
+                        ArchiveDataType::Attachments,
+                        &b.archive_id,
+                        &attachment.id,
+                    ).await?,
+                };
+                let data = blob::fetch(&self.client, &curr_access, &b.blob_id).await?;
+                access = Some(curr_access);
+                yield crypto::aes_decrypt(&attachment.session_key, &data)?;
+            }
+        }
+    }
+
+    /// Like [`Client::attachment_stream`], but as [`AsyncRead`], e.g. to copy it into a file.
+    pub fn attachment_reader<'a>(
+        &'a self,
+        attachment: &'a Attachment,
+    ) -> impl AsyncRead + Unpin + 'a {
+        StreamReader::new(Box::pin(self.attachment_stream(attachment)).map(|chunk| {
+            chunk
+                .map(std::io::Cursor::new)
+                .map_err(std::io::Error::other)
+        }))
+    }
+
+    /// Downloads and decrypts the whole data of `attachment`.
+    pub async fn download_attachment(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity((attachment.size as usize).min(MAX_PREALLOCATION));
+        let chunks = self.attachment_stream(attachment);
+        pin_mut!(chunks);
+        while let Some(chunk) = chunks.next().await {
+            data.extend(chunk?);
+        }
+        Ok(data)
+    }
+
+    /// Decrypts the mails of `mails`, e.g. from [`Client::get_mails`], with up to `concurrency`
+    /// mails in flight at once.
+    ///
+    /// The decrypted mails are yielded in the order they finish. If the server rate limits the
+    /// client, all pending requests wait for the rate limit to pass.
+    pub fn decrypt_stream<'a, S>(
+        &'a self,
+        mails: S,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<(Mail, MailContent)>> + 'a
+    where
+        S: Stream<Item = Result<Mail>> + 'a,
+    {
+        mails
+            .map(move |mail| async move {
+                let mail = mail?;
+                let content = self.decrypt(&mail).await?;
+                Ok((mail, content))
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
     pub async fn set_read_status(&self, mail: &mut Mail, read_status: ReadStatus) -> Result<()> {
         if mail.read_status == read_status {
             return Ok(());
         }
 
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let unread = read_status == ReadStatus::Unread;
+        unread_mail_state::send(&self.client, std::slice::from_ref(&mail.id), unread).await?;
         mail.read_status = read_status;
-        mail::update(&self.client, &mail).await?;
+        self.cache_mail(mail).await;
         Ok(())
     }
 
+    /// Marks `mails` as read or unread, without fetching them first.
+    ///
+    /// Uses far fewer requests than [`Client::set_read_status`] for many mails, see
+    /// [`Client::move_mails`] for how failures are reported.
+    pub async fn set_read_status_batch(
+        &self,
+        mails: &[IdTuple],
+        read_status: ReadStatus,
+    ) -> Result<BatchResult> {
+        let unread = read_status == ReadStatus::Unread;
+        let result = self
+            .run_batched(mails, |batch| async move {
+                unread_mail_state::send(&self.client, &batch, unread).await
+            })
+            .await?;
+        for id in &result.succeeded {
+            if let Some(mut entry) = self.cached(id).await {
+                entry.mail.read_status = read_status.clone();
+                self.store(&entry).await;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Moves `mails` into `target_folder`.
+    ///
+    /// The mails are moved in batches. If a batch fails, its mails are retried one by one, so
+    /// the result tells exactly which mails could not be moved. Errors which affect every mail,
+    /// e.g. an expired session, are returned instead.
+    pub async fn move_mails(
+        &self,
+        mails: &[IdTuple],
+        target_folder: &MailFolder,
+    ) -> Result<BatchResult> {
+        let result = self
+            .run_batched(mails, |batch| async move {
+                move_mail::send(&self.client, &batch, &target_folder.id).await
+            })
+            .await?;
+        // the mails live in another list now, so the cached entries are stale
+        for id in &result.succeeded {
+            self.uncache_mail(id).await;
+        }
+        Ok(result)
+    }
+
+    /// Moves `mails` into the Trash folder.
+    pub async fn trash_mails(&self, mails: &[IdTuple]) -> Result<BatchResult> {
+        let trash = self
+            .folders
+            .iter()
+            .find(|folder| folder.folder_type == MailFolderType::Trash)
+            .ok_or_else(|| Error::MissingData("Could not find trash folder".into()))?;
+        self.move_mails(mails, trash).await
+    }
+
+    /// Deletes `mails` for good.
+    ///
+    /// Like in the web app, only mails in Trash or Spam can be deleted permanently, all other
+    /// mails fail and need to be trashed with [`Client::trash_mails`] first.
+    pub async fn delete_mails_permanently(&self, mails: &[IdTuple]) -> Result<BatchResult> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let mut result = BatchResult::default();
+        let mut by_folder: Vec<(&MailFolder, Vec<IdTuple>)> = Vec::new();
+        for id in mails {
+            let Some(folder) = self.folders.iter().find(|folder| folder.mails == id.0) else {
+                let e = Error::MissingData(format!("Mail {:?} is not in a known folder", id));
+                result.failed.push((id.clone(), e));
+                continue;
+            };
+            if !matches!(
+                folder.folder_type,
+                MailFolderType::Trash | MailFolderType::Spam
+            ) {
+                let e = Error::InvalidData(format!("Mail {:?} is not in Trash or Spam", id));
+                result.failed.push((id.clone(), e));
+                continue;
+            }
+            match by_folder.iter_mut().find(|(f, _)| f.id == folder.id) {
+                Some((_, ids)) => ids.push(id.clone()),
+                None => by_folder.push((folder, vec![id.clone()])),
+            }
+        }
+
+        for (folder, ids) in by_folder {
+            let deleted = self
+                .run_batched(&ids, |batch| async move {
+                    delete_mail::send(&self.client, &batch, &folder.id).await
+                })
+                .await?;
+            for id in &deleted.succeeded {
+                self.uncache_mail(id).await;
+            }
+            result.succeeded.extend(deleted.succeeded);
+            result.failed.extend(deleted.failed);
+        }
+        Ok(result)
+    }
+
+    async fn run_batched<F, Fut>(&self, mails: &[IdTuple], operation: F) -> Result<BatchResult>
+    where
+        F: Fn(Vec<IdTuple>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let mut result = BatchResult::default();
+        for batch in mails.chunks(api::MAX_BATCH_MAILS) {
+            let e = match operation(batch.to_vec()).await {
+                Ok(()) => {
+                    result.succeeded.extend_from_slice(batch);
+                    continue;
+                }
+                Err(e) if Self::is_fatal(&e) => return Err(e),
+                Err(e) => e,
+            };
+            if let [id] = batch {
+                result.failed.push((id.clone(), e));
+                continue;
+            }
+
+            warn!(
+                "Batch of {} mails failed, retrying one by one: {}",
+                batch.len(),
+                e
+            );
+            for id in batch {
+                match operation(vec![id.clone()]).await {
+                    Ok(()) => result.succeeded.push(id.clone()),
+                    Err(e) if Self::is_fatal(&e) => return Err(e),
+                    Err(e) => result.failed.push((id.clone(), e)),
+                }
+            }
+        }
+        Ok(result)
+    }
+
     pub fn get_websocket_connector(&self) -> Result<WebSocketConnector> {
         WebSocketConnector::from_url(&self.client, &self.user.id)
     }
+
+    /// Like [`Client::subscribe`], but yields every single event of the entity updates.
+    pub fn events(&self) -> impl Stream<Item = Result<EntityEvent>> + '_ {
+        try_stream! {
+            if self.offline {
+                Err(Error::Offline)?;
+            }
+            let messages = self.subscribe();
+            pin_mut!(messages);
+            while let Some(message) = messages.next().await {
+                if let WebSocketMessage::EntityUpdate(update) = message? {
+                    for event in update.events() {
+                        yield event;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Subscribes to the websocket messages of the user, e.g. the entity updates of every group.
+    ///
+    /// Whenever the websocket fails, it is reconnected with a jittered exponential backoff and the
+    /// entity event batches missed in the meantime are replayed, so no update is lost. The stream
+    /// only ends after yielding an error which a reconnect cannot fix, e.g. an expired session.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<WebSocketMessage>> + '_ {
+        stream! {
+            if self.offline {
+                yield Err(Error::Offline);
+                return;
+            }
+            let mut last_ids = match self.fetch_last_event_ids().await {
+                Ok(last_ids) => last_ids,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            let connector = match self.get_websocket_connector() {
+                Ok(connector) => connector,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+
+            let mut backoff = Backoff::new();
+            loop {
+                debug!("Connecting to websocket");
+                match connector.connect().await {
+                    Ok(mut socket) => {
+                        let connected_at = std::time::Instant::now();
+                        // live updates must not move past batches which were not replayed yet
+                        let replayed = match self.fetch_missed_updates(&last_ids).await {
+                            Ok(updates) => {
+                                for update in updates {
+                                    last_ids.insert(
+                                        update.event_batch_owner.clone(),
+                                        update.event_batch_id.clone(),
+                                    );
+                                    yield Ok(WebSocketMessage::EntityUpdate(update));
+                                }
+                                true
+                            }
+                            Err(e) if Self::is_fatal(&e) => {
+                                yield Err(e);
+                                return;
+                            }
+                            Err(e) => {
+                                warn!("Could not fetch missed updates: {}", e);
+                                false
+                            }
+                        };
+
+                        if replayed {
+                            while let Some(message) = socket.next().await {
+                                match message {
+                                    Ok(WebSocketMessage::EntityUpdate(update)) => {
+                                        backoff.reset();
+                                        if last_ids
+                                            .get(&update.event_batch_owner)
+                                            .is_some_and(|last| *last >= update.event_batch_id)
+                                        {
+                                            continue;
+                                        }
+                                        last_ids.insert(
+                                            update.event_batch_owner.clone(),
+                                            update.event_batch_id.clone(),
+                                        );
+                                        yield Ok(WebSocketMessage::EntityUpdate(update));
+                                    }
+                                    Ok(message) => {
+                                        backoff.reset();
+                                        yield Ok(message);
+                                    }
+                                    Err(Error::Deserialization(e)) => {
+                                        backoff.reset();
+                                        warn!("Skipping websocket message: {}", e);
+                                    }
+                                    Err(e) => {
+                                        warn!("Websocket failed: {}", e);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        if connected_at.elapsed() >= STABLE_CONNECTION {
+                            backoff.reset();
+                        }
+                    }
+                    Err(e) if Self::is_fatal(&e) => {
+                        yield Err(e);
+                        return;
+                    }
+                    Err(e) => warn!("Could not connect to websocket: {}", e),
+                }
+
+                let delay = backoff.next_delay();
+                warn!("Reconnecting in {} seconds", delay.as_secs());
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
+    /// Returns the mails in the watched folders which were created, updated or deleted since the
+    /// last call with `state`.
+    ///
+    /// Only the entity event batches since the last processed batch of each group are fetched, so
+    /// `state` should be persisted between polls. On the first call with an empty `state` only the
+    /// current position is recorded, use [`Client::get_mails`] for the initial listing.
+    pub async fn sync(&self, state: &mut SyncState) -> Result<Vec<MailChange>> {
+        if self.offline {
+            return Err(Error::Offline);
+        }
+
+        let mut last_ids = state.last_event_ids.clone();
+        let mut changes: Vec<(IdTuple, OperationType)> = Vec::new();
+        for group in self.user.group_ids() {
+            let Some(last_id) = last_ids.get(group) else {
+                last_ids.insert(group.clone(), self.fetch_last_event_id(group).await?);
+                continue;
+            };
+
+            let batches = entity_event_batch::fetch_since(&self.client, group, last_id).await?;
+            for batch in batches {
+                let update = EntityUpdate::from(batch);
+                for event in update.events() {
+                    if let EntityEvent::Mail { operation, id } = event {
+                        if self.is_watched(&id) {
+                            Self::record_change(&mut changes, id, operation);
+                        }
+                    }
+                }
+                last_ids.insert(update.event_batch_owner, update.event_batch_id);
+            }
+        }
+
+        let mut mail_changes = Vec::with_capacity(changes.len());
+        for (id, operation) in changes {
+            let mail = match operation {
+                OperationType::Delete => {
+                    self.uncache_mail(&id).await;
+                    mail_changes.push(MailChange::Deleted(id));
+                    continue;
+                }
+                _ => match self.get_mail(&id).await {
+                    Ok(mail) => mail,
+                    Err(Error::Status(StatusCode::NOT_FOUND)) => {
+                        debug!("Mail {:?} is already gone", id);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            match operation {
+                OperationType::Create => mail_changes.push(MailChange::Created(mail)),
+                _ => mail_changes.push(MailChange::Updated(mail)),
+            }
+        }
+
+        state.last_event_ids = last_ids;
+        Ok(mail_changes)
+    }
+
+    /// Merges `operation` into the pending changes, so every mail shows up only once.
+    fn record_change(
+        changes: &mut Vec<(IdTuple, OperationType)>,
+        id: IdTuple,
+        operation: OperationType,
+    ) {
+        let Some(pos) = changes.iter().position(|(i, _)| *i == id) else {
+            changes.push((id, operation));
+            return;
+        };
+
+        match (changes[pos].1, operation) {
+            (OperationType::Create, OperationType::Delete) => {
+                changes.remove(pos);
+            }
+            (OperationType::Create, OperationType::Update) => {}
+            (_, operation) => changes[pos].1 = operation,
+        }
+    }
+
+    async fn fetch_last_event_id(&self, group: &Id) -> Result<Id> {
+        Ok(entity_event_batch::fetch_last_id(&self.client, group)
+            .await?
+            .unwrap_or_else(|| "------------".to_string()))
+    }
+
+    async fn fetch_last_event_ids(&self) -> Result<HashMap<Id, Id>> {
+        let mut last_ids = HashMap::new();
+        for group in self.user.group_ids() {
+            last_ids.insert(group.clone(), self.fetch_last_event_id(group).await?);
+        }
+        Ok(last_ids)
+    }
+
+    async fn fetch_missed_updates(&self, last_ids: &HashMap<Id, Id>) -> Result<Vec<EntityUpdate>> {
+        let mut updates = Vec::new();
+        for (group, last_id) in last_ids {
+            let batches = entity_event_batch::fetch_since(&self.client, group, last_id).await?;
+            updates.extend(batches.into_iter().map(EntityUpdate::from));
+        }
+        Ok(updates)
+    }
+
+    fn is_fatal(e: &Error) -> bool {
+        matches!(
+            e,
+            Error::Authentication | Error::NotAuthenticated | Error::SessionExpired
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Endpoint;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const KEY: &str = "AAAAAAAAAAAAAAAAAAAAAA==";
+
+    fn config(base_url: url::Url) -> config::Account {
+        config::Account {
+            email_address: "user@tuta.com".into(),
+            password: "password".into(),
+            watch_spam: false,
+            watch_folders: Vec::new(),
+            show_name: false,
+            show_subject: false,
+            show_body: false,
+            show_headers: false,
+            totp_secret: None,
+            endpoint: Endpoint {
+                base_url,
+                ..Default::default()
+            },
+            cache_dir: None,
+        }
+    }
+
+    fn client(base_url: url::Url) -> Client {
+        let config = config(base_url);
+        let mut http_client = HttpClient::new(config.endpoint.clone());
+        http_client.set_access_token("token".into());
+        Client {
+            config,
+            client: http_client,
+            inboxes: Vec::new(),
+            folders: Vec::new(),
+            labels: Vec::new(),
+            mail_group: Id::new(),
+            folder_list: Id::new(),
+            user: User::offline("user".into()),
+            access_key: crypto::generate_key(),
+            cache: None,
+            offline: false,
+            bucket_keys: Default::default(),
+            private_keys: Default::default(),
+        }
+    }
+
+    /// Serves a single request with `body` as JSON response.
+    async fn serve_once(body: &'static str) -> url::Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        url::Url::parse(&format!("http://{}", addr)).unwrap()
+    }
+
+    #[test]
+    fn resolve_external_bucket_without_group_key() {
+        let client = client(url::Url::parse("http://localhost").unwrap());
+        let bucket_permission: BucketPermission = serde_json::from_str(&format!(
+            r#"{{"_format":"0","type":"3","_ownerGroup":"group","ownerEncBucketKey":"{}",
+                "pubEncBucketKey":null,"symEncBucketKey":null,"group":"group"}}"#,
+            KEY
+        ))
+        .unwrap();
+        assert!(matches!(
+            client.resolve_external_bucket(&bucket_permission),
+            Err(Error::MissingData(_))
+        ));
+    }
+
+    fn folder(folder_type: &str) -> Folder {
+        serde_json::from_str(&format!(
+            r#"{{"_format":"0","folderType":"{}","_id":["list","folder"],"mails":"mails",
+                "name":"","_ownerEncSessionKey":"{}","_ownerGroup":"group"}}"#,
+            folder_type, KEY
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn folder_session_key_without_group_key() {
+        let folder = folder("1");
+        assert!(matches!(
+            Client::folder_session_key(&User::offline("user".into()), &folder),
+            Err(Error::MissingData(_))
+        ));
+    }
+
+    #[test]
+    fn undecryptable_folders() {
+        let user = User::offline("user".into());
+        let inbox = Client::decrypt_folder(&user, folder("1")).unwrap();
+        assert_eq!(inbox.name, "Inbox");
+        assert!(matches!(
+            Client::decrypt_folder(&user, folder("0")),
+            Err(Error::MissingData(_))
+        ));
+    }
+
+    #[test]
+    fn decompress_grows_buffer() {
+        let text = "tuta ".repeat(1000);
+        let compressed = lz4_flex::compress(text.as_bytes());
+        assert!(compressed.len() * 6 < text.len());
+        assert_eq!(Client::decompress(&compressed).unwrap(), text);
+    }
+
+    #[tokio::test]
+    async fn totp_challenge_without_code_fails() {
+        let client = client(url::Url::parse("http://localhost").unwrap());
+        let session: Session = serde_json::from_str(
+            r#"{"_format":"0","accessToken":"token","user":"user","challenges":[{"type":"1"}]}"#,
+        )
+        .unwrap();
+        let result =
+            Client::complete_second_factor(&client.client, &client.config, &session, || None).await;
+        assert!(matches!(result, Err(Error::Authentication)));
+    }
+
+    #[tokio::test]
+    async fn online_only_streams_fail_offline() {
+        let mut client = client(url::Url::parse("http://localhost").unwrap());
+        client.offline = true;
+        assert!(matches!(
+            client.sync(&mut SyncState::default()).await,
+            Err(Error::Offline)
+        ));
+        let messages = client.subscribe();
+        pin_mut!(messages);
+        assert!(matches!(messages.next().await, Some(Err(Error::Offline))));
+        let events = client.events();
+        pin_mut!(events);
+        assert!(matches!(events.next().await, Some(Err(Error::Offline))));
+    }
+
+    #[tokio::test]
+    async fn offline_client_reads_cached_mails() {
+        let dir = crate::cache::tests::temp_dir();
+        let config = config::Account {
+            show_subject: true,
+            show_body: true,
+            cache_dir: Some(dir.clone()),
+            ..config(url::Url::parse("http://localhost").unwrap())
+        };
+        let salt = types::Salt {
+            salt: [1; 16],
+            kdf_version: types::KdfType::Bcrypt,
+        };
+        MailCache::save_salt(&dir, &salt).await.unwrap();
+        let passphrase_key =
+            crypto::create_user_passphrase_key(&config.password, &salt.salt, &salt.kdf_version)
+                .unwrap();
+        let cache = MailCache::open(&dir, crypto::derive_cache_key(&passphrase_key))
+            .await
+            .unwrap();
+        cache
+            .save_index(&CacheIndex {
+                user_id: "user".into(),
+                inboxes: vec!["inbox".into()],
+            })
+            .await
+            .unwrap();
+        let session_key = crypto::generate_key();
+        let mut mail = crate::types::tests::mail("inbox", "a");
+        mail.subject = crypto::aes_encrypt(&session_key, b"Hello").unwrap();
+        let mut entry = CachedMail::new(mail);
+        entry.session_key = Some(session_key);
+        let body = lz4_flex::compress(b"Cached body");
+        entry.body = Some(crypto::aes_encrypt(&session_key, &body).unwrap());
+        cache.put(&entry).await.unwrap();
+
+        let client = Client::offline(&config).await.unwrap();
+        let mails = client.get_mails();
+        pin_mut!(mails);
+        let mail = mails.next().await.unwrap().unwrap();
+        assert!(mails.next().await.is_none());
+        let content = client.decrypt(&mail).await.unwrap();
+        assert_eq!(content.subject.as_deref(), Some("Hello"));
+        assert_eq!(content.body.as_deref(), Some("Cached body"));
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[test]
+    fn record_change_merges_operations() {
+        let id = |n: &str| ("list".to_string(), n.to_string());
+        let mut changes = Vec::new();
+        Client::record_change(&mut changes, id("a"), OperationType::Create);
+        Client::record_change(&mut changes, id("a"), OperationType::Update);
+        Client::record_change(&mut changes, id("b"), OperationType::Update);
+        Client::record_change(&mut changes, id("b"), OperationType::Delete);
+        assert_eq!(
+            changes,
+            vec![
+                (id("a"), OperationType::Create),
+                (id("b"), OperationType::Delete)
+            ]
+        );
+
+        Client::record_change(&mut changes, id("a"), OperationType::Delete);
+        assert_eq!(changes, vec![(id("b"), OperationType::Delete)]);
+    }
+
+    #[tokio::test]
+    async fn private_key_without_group_key() {
+        let base_url =
+            serve_once(r#"{"_id":"group","keys":[{"symEncPrivKey":"AAAAAAAAAAAAAAAAAAAAAA=="}]}"#)
+                .await;
+        let client = client(base_url);
+        assert!(matches!(
+            client.private_key(&"group".into()).await,
+            Err(Error::MissingData(_))
+        ));
+    }
 }
@@ -1,4 +1,6 @@
 use serde::Deserialize;
+use std::path::PathBuf;
+use url::Url;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Account {
@@ -6,10 +8,50 @@ pub struct Account {
     pub password: String,
     #[serde(default)]
     pub watch_spam: bool,
+    /// Paths of the folders to watch, e.g. `Alerts/Prod`. If empty, the Inbox and, with
+    /// `watch_spam`, the Spam folder are watched.
+    #[serde(default)]
+    pub watch_folders: Vec<String>,
     #[serde(default)]
     pub show_name: bool,
     #[serde(default)]
     pub show_subject: bool,
     #[serde(default)]
     pub show_body: bool,
+    #[serde(default)]
+    pub show_headers: bool,
+    /// Base32 encoded secret used to generate TOTP codes if the account uses two-factor
+    /// authentication.
+    #[serde(default)]
+    pub totp_secret: Option<String>,
+    #[serde(default)]
+    pub endpoint: Endpoint,
+    /// Directory of the encrypted mail cache, caching is disabled if not set.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Server the client talks to and the versions it reports to it.
+///
+/// Defaults to the public tuta instance. Point `base_url` somewhere else to use a self-hosted
+/// instance or a local mock server. A path prefix in `base_url` (e.g. `https://host/tuta/`) is
+/// kept, and `http` base urls connect the event socket over `ws` instead of `wss`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Endpoint {
+    pub base_url: Url,
+    pub client_identifier: String,
+    pub client_version: String,
+    pub model_version: String,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint {
+            base_url: Url::parse(crate::api::BASE_URL).expect("BASE_URL is a valid url"),
+            client_identifier: crate::api::CLIENT.to_string(),
+            client_version: crate::api::CLIENT_VERSION.to_string(),
+            model_version: crate::api::MODEL_VERSION.to_string(),
+        }
+    }
 }
@@ -1,14 +1,18 @@
-use crate::types::Aes128Key;
-use aes::cipher::block_padding::Pkcs7;
-use aes::cipher::{BlockDecryptMut, KeyIvInit};
-use anyhow::{bail, Result};
+use crate::types::{Aes128Key, Aes256Key, KdfType};
+use crate::{Error, Result};
+use aes::cipher::block_padding::{NoPadding, Pkcs7};
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use hmac::{Hmac, Mac};
-use num_traits::cast::FromPrimitive;
 use rsa::RsaPrivateKey;
 use sha2::Digest;
 
 const MAC_SIZE: usize = 32;
+const IV_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 16;
 const RSA_KEY_LENGTH_BITS: usize = 2048;
+const ARGON2ID_ITERATIONS: u32 = 4;
+const ARGON2ID_MEMORY_IN_KIB: u32 = 32 * 1024;
+const ARGON2ID_PARALLELISM: u32 = 1;
 
 pub struct SubKeys {
     cipher: Aes128Key,
@@ -38,7 +42,69 @@ impl SubKeys {
     }
 }
 
-pub fn create_user_passphrase_key(passphrase: &str, salt: &Aes128Key) -> Aes128Key {
+/// Key derived from the password of the user, which unlocks the user group key.
+///
+/// Accounts using bcrypt have 128 bit keys, accounts using Argon2id have 256 bit keys.
+pub enum PassphraseKey {
+    Aes128(Aes128Key),
+    Aes256(Aes256Key),
+}
+
+impl PassphraseKey {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            PassphraseKey::Aes128(key) => key,
+            PassphraseKey::Aes256(key) => key,
+        }
+    }
+
+    /// Decrypts a key encrypted with this key, 256 bit keys always encrypt with an IV and a MAC.
+    pub fn decrypt_key(&self, message: &[u8]) -> Result<Aes128Key> {
+        let decrypted = match self {
+            PassphraseKey::Aes128(key) => {
+                let message = message
+                    .try_into()
+                    .map_err(|_| Error::Crypto("encrypted key must be 16 bytes".into()))?;
+                return Ok(decrypt_key(key, message));
+            }
+            PassphraseKey::Aes256(key) => aes256_decrypt(key, message, false)?,
+        };
+        decrypted
+            .try_into()
+            .map_err(|_| Error::Crypto("decrypted key must be 16 bytes".into()))
+    }
+}
+
+pub fn create_user_passphrase_key(
+    passphrase: &str,
+    salt: &Aes128Key,
+    kdf_type: &KdfType,
+) -> Result<PassphraseKey> {
+    match kdf_type {
+        KdfType::Bcrypt => Ok(PassphraseKey::Aes128(create_bcrypt_key(passphrase, salt))),
+        KdfType::Argon2id => Ok(PassphraseKey::Aes256(create_argon2id_key(
+            passphrase, salt,
+        )?)),
+    }
+}
+
+fn create_argon2id_key(passphrase: &str, salt: &Aes128Key) -> Result<Aes256Key> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+    let params = Params::new(
+        ARGON2ID_MEMORY_IN_KIB,
+        ARGON2ID_ITERATIONS,
+        ARGON2ID_PARALLELISM,
+        Some(32),
+    )
+    .map_err(|e| Error::Crypto(format!("invalid argon2 parameters: {}", e)))?;
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Crypto(format!("argon2 key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+fn create_bcrypt_key(passphrase: &str, salt: &Aes128Key) -> Aes128Key {
     let mut hasher = sha2::Sha256::new();
     hasher.update(passphrase);
     let user_passphrase_hash = hasher.finalize();
@@ -48,6 +114,25 @@ pub fn create_user_passphrase_key(passphrase: &str, salt: &Aes128Key) -> Aes128K
     user_passphrase_key
 }
 
+pub fn generate_key() -> Aes128Key {
+    rand::random()
+}
+
+/// Generates the current TOTP code for a base32 encoded secret.
+pub fn totp_code(secret: &str) -> Result<String> {
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let secret = totp_rs::Secret::Encoded(secret)
+        .to_bytes()
+        .map_err(|e| Error::InvalidData(format!("TOTP secret is not valid base32: {:?}", e)))?;
+    let totp = totp_rs::TOTP::new_unchecked(totp_rs::Algorithm::SHA1, 6, 1, 30, secret);
+    totp.generate_current()
+        .map_err(|e| Error::InvalidData(format!("System time is invalid: {}", e)))
+}
+
 pub fn decrypt_key(key: &Aes128Key, message: &Aes128Key) -> Aes128Key {
     use aes::cipher::{BlockDecrypt, KeyInit};
     let mut output = [0; 16];
@@ -76,28 +161,114 @@ pub fn aes_decrypt(key: &Aes128Key, message: &[u8]) -> Result<Vec<u8>> {
     let use_mac = message.len() % 2 == 1;
     let sub_keys = SubKeys::new(*key, use_mac);
 
-    let message_without_mac;
-    if use_mac {
-        message_without_mac = &message[1..message.len() - MAC_SIZE];
-        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(&sub_keys.mac.unwrap())?;
-        mac.update(message_without_mac);
-        if mac
-            .verify_slice(&message[message.len() - MAC_SIZE..])
-            .is_err()
-        {
-            bail!("message could not be verified");
-        }
-    } else {
-        message_without_mac = &message;
+    let min_len = IV_SIZE + BLOCK_SIZE + if use_mac { 1 + MAC_SIZE } else { 0 };
+    if message.len() < min_len {
+        return Err(Error::Crypto(format!(
+            "message too short, expected at least {} bytes, but was: {}",
+            min_len,
+            message.len()
+        )));
     }
 
+    let message_without_mac = match sub_keys.mac {
+        Some(mac_key) => verify_mac(&mac_key, message)?,
+        None => message,
+    };
+
     type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
-    let msg = Aes128CbcDec::new(sub_keys.cipher[..].into(), message_without_mac[..16].into())
-        .decrypt_padded_vec_mut::<Pkcs7>(&message_without_mac[16..])?;
+    let (iv, ciphertext) = message_without_mac.split_at(IV_SIZE);
+    let msg = Aes128CbcDec::new(sub_keys.cipher[..].into(), iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|e| Error::Crypto(e.to_string()))?;
 
     Ok(msg)
 }
 
+/// Decrypts a message encrypted with a 256 bit key, which always carries a MAC.
+///
+/// The cipher and MAC keys are derived with SHA-512, keys are encrypted without padding.
+pub fn aes256_decrypt(key: &Aes256Key, message: &[u8], use_padding: bool) -> Result<Vec<u8>> {
+    let min_len = 1 + IV_SIZE + BLOCK_SIZE + MAC_SIZE;
+    if message.len() < min_len || message.len().is_multiple_of(2) {
+        return Err(Error::Crypto(format!(
+            "message must have a MAC and at least {} bytes, but was: {}",
+            min_len,
+            message.len()
+        )));
+    }
+
+    let mut hasher = sha2::Sha512::new();
+    hasher.update(key);
+    let hash = hasher.finalize();
+    let (cipher_key, mac_key) = hash.split_at(32);
+    let message_without_mac = verify_mac(mac_key, message)?;
+
+    type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+    let (iv, ciphertext) = message_without_mac.split_at(IV_SIZE);
+    let decryptor = Aes256CbcDec::new(cipher_key.into(), iv.into());
+    let msg = if use_padding {
+        decryptor.decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+    } else {
+        decryptor.decrypt_padded_vec_mut::<NoPadding>(ciphertext)
+    };
+    msg.map_err(|e| Error::Crypto(e.to_string()))
+}
+
+/// Checks the MAC at the end of `message` and returns the IV and ciphertext.
+fn verify_mac<'a>(mac_key: &[u8], message: &'a [u8]) -> Result<&'a [u8]> {
+    let message_without_mac = &message[1..message.len() - MAC_SIZE];
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(mac_key).map_err(|e| Error::Crypto(e.to_string()))?;
+    mac.update(message_without_mac);
+    mac.verify_slice(&message[message.len() - MAC_SIZE..])
+        .map_err(|_| Error::InvalidMac)?;
+    Ok(message_without_mac)
+}
+
+/// Encrypts `message` with a random IV and appends a MAC, the counterpart of [`aes_decrypt`].
+pub fn aes_encrypt(key: &Aes128Key, message: &[u8]) -> Result<Vec<u8>> {
+    let sub_keys = SubKeys::new(*key, true);
+    let iv: [u8; IV_SIZE] = rand::random();
+
+    type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+    let ciphertext = Aes128CbcEnc::new(sub_keys.cipher[..].into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(message);
+
+    let mut output = Vec::with_capacity(1 + IV_SIZE + ciphertext.len() + MAC_SIZE);
+    output.push(1);
+    output.extend_from_slice(&iv);
+    output.extend_from_slice(&ciphertext);
+
+    let mac_key = sub_keys.mac.expect("sub keys were created with a mac key");
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_from_slice(&mac_key).map_err(|e| Error::Crypto(e.to_string()))?;
+    mac.update(&output[1..]);
+    output.extend_from_slice(&mac.finalize().into_bytes());
+    Ok(output)
+}
+
+/// Derives the key of the local mail cache from the passphrase key.
+pub fn derive_cache_key(passphrase_key: &PassphraseKey) -> Aes128Key {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(b"tuta_poll mail cache");
+    hasher.update(passphrase_key.as_bytes());
+    let hash = hasher.finalize();
+    let mut key = [0; 16];
+    key.copy_from_slice(&hash[..16]);
+    key
+}
+
+/// Decrypts an encrypted string value, empty values stay empty.
+///
+/// Invalid UTF-8 is replaced instead of failing, the text is only meant to be displayed.
+pub fn decrypt_string(key: &Aes128Key, message: &[u8]) -> Result<String> {
+    if message.is_empty() {
+        return Ok(String::new());
+    }
+    let decrypted = aes_decrypt(key, message)?;
+    Ok(String::from_utf8_lossy(&decrypted).into_owned())
+}
+
 pub fn decrypt_rsa_key(key: &Aes128Key, message: &[u8]) -> Result<RsaPrivateKey> {
     let decrypt = aes_decrypt(key, message)?;
     u8_to_key(&decrypt)
@@ -107,36 +278,133 @@ fn u8_to_key(enc_key: &[u8]) -> Result<RsaPrivateKey> {
     let mut key = Vec::new();
     let mut pos: usize = 0;
     while pos < enc_key.len() {
-        let next_param_len =
-            u16::from_be_bytes(enc_key[pos..pos + 2].try_into().unwrap()) as usize / 2;
+        let invalid = || Error::Crypto("key params exceed the key length".into());
+        let len_bytes = enc_key.get(pos..pos + 2).ok_or_else(invalid)?;
+        let next_param_len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize / 2;
         pos += 2;
-        let value = rsa::BigUint::from_bytes_be(&enc_key[pos..pos + next_param_len]);
-        key.push(value);
+        let param = enc_key.get(pos..pos + next_param_len).ok_or_else(invalid)?;
+        key.push(rsa::BigUint::from_bytes_be(param));
         pos += next_param_len;
     }
 
-    if key.len() != 1 && key.len() != 7 {
-        bail!("invalid key params");
+    if key.len() != 7 {
+        return Err(Error::Crypto("invalid key params".into()));
     }
 
     let bit_length = key[0].bits();
-    if bit_length < RSA_KEY_LENGTH_BITS - 1 || bit_length > RSA_KEY_LENGTH_BITS {
-        bail!(
+    if !(RSA_KEY_LENGTH_BITS - 1..=RSA_KEY_LENGTH_BITS).contains(&bit_length) {
+        return Err(Error::Crypto(format!(
             "invalid key length, expected: around {}, but was: {}",
-            RSA_KEY_LENGTH_BITS,
-            bit_length
-        )
+            RSA_KEY_LENGTH_BITS, bit_length
+        )));
     }
 
-    Ok(RsaPrivateKey::from_components(
+    RsaPrivateKey::from_components(
         key[0].clone(),
-        rsa::BigUint::from_u32(65537).unwrap(),
+        rsa::BigUint::from(65537u32),
         key[1].clone(),
         key[2..4].to_vec(),
-    )?)
+    )
+    .map_err(|e| Error::Crypto(e.to_string()))
 }
 
 pub fn rsa_decrypt(key: &RsaPrivateKey, message: &[u8]) -> Result<Vec<u8>> {
     let padding = rsa::Oaep::new::<sha2::Sha256>();
-    Ok(key.decrypt(padding, message)?)
+    key.decrypt(padding, message)
+        .map_err(|e| Error::Crypto(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: Aes128Key = [7; 16];
+
+    #[test]
+    fn aes_round_trip() {
+        let encrypted = aes_encrypt(&KEY, b"hello tuta").unwrap();
+        assert_eq!(aes_decrypt(&KEY, &encrypted).unwrap(), b"hello tuta");
+    }
+
+    #[test]
+    fn aes_decrypt_rejects_truncated_message() {
+        let encrypted = aes_encrypt(&KEY, b"hello tuta").unwrap();
+        let truncated = &encrypted[..1 + IV_SIZE + BLOCK_SIZE];
+        assert!(
+            matches!(aes_decrypt(&KEY, truncated), Err(Error::Crypto(e)) if e.contains("too short"))
+        );
+    }
+
+    #[test]
+    fn aes_decrypt_rejects_flipped_mac() {
+        let mut encrypted = aes_encrypt(&KEY, b"hello tuta").unwrap();
+        *encrypted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            aes_decrypt(&KEY, &encrypted),
+            Err(Error::InvalidMac)
+        ));
+    }
+
+    #[test]
+    fn aes_decrypt_rejects_bad_padding() {
+        use aes::cipher::block_padding::NoPadding;
+        type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+        let iv = [0; IV_SIZE];
+        let ciphertext = Aes128CbcEnc::new(KEY[..].into(), &iv.into())
+            .encrypt_padded_vec_mut::<NoPadding>(&[0; BLOCK_SIZE]);
+        let message = [&iv[..], &ciphertext].concat();
+        assert!(matches!(aes_decrypt(&KEY, &message), Err(Error::Crypto(_))));
+    }
+
+    #[test]
+    fn decrypt_string_replaces_invalid_utf8() {
+        let encrypted = aes_encrypt(&KEY, &[b'a', 0xff, b'b']).unwrap();
+        assert_eq!(decrypt_string(&KEY, &encrypted).unwrap(), "a\u{fffd}b");
+        assert_eq!(decrypt_string(&KEY, &[]).unwrap(), "");
+    }
+
+    #[test]
+    fn argon2id_key_known_answer() {
+        let salt = core::array::from_fn(|i| i as u8);
+        let key = create_argon2id_key("password", &salt).unwrap();
+        assert_eq!(
+            key,
+            [
+                0x2c, 0xb6, 0xa1, 0x07, 0xa9, 0xd3, 0xbf, 0x7e, 0x09, 0x37, 0x9e, 0x81, 0x67, 0x6c,
+                0x04, 0x6e, 0x05, 0xaf, 0xa6, 0x2f, 0x66, 0xb3, 0x22, 0x05, 0x5a, 0xdc, 0xa8, 0x15,
+                0xc2, 0x3a, 0x7e, 0x9d
+            ]
+        );
+    }
+
+    #[test]
+    fn decrypt_key_with_256_bit_key() {
+        use base64::{engine::general_purpose as engines, Engine as _};
+        let key = PassphraseKey::Aes256(core::array::from_fn(|i| i as u8));
+        let mut message = engines::STANDARD
+            .decode("ARAREhMUFRYXGBkaGxwdHh84A0lXACmRDDTyHgjHoKTODkLCrGendFN4nF0rLFbV/jSqmVEZ3xEFqhAA8kzLXVE=")
+            .unwrap();
+        let mut expected = [0x5a; 16];
+        expected[..8].fill(0xa5);
+        assert_eq!(key.decrypt_key(&message).unwrap(), expected);
+
+        message[20] ^= 1;
+        assert!(matches!(key.decrypt_key(&message), Err(Error::InvalidMac)));
+        assert!(matches!(
+            key.decrypt_key(&message[..16]),
+            Err(Error::Crypto(_))
+        ));
+    }
+
+    #[test]
+    fn u8_to_key_rejects_overrunning_length() {
+        let enc_key = [0x00, 0x08, 0x01, 0x02];
+        assert!(matches!(u8_to_key(&enc_key), Err(Error::Crypto(_))));
+    }
+
+    #[test]
+    fn u8_to_key_rejects_missing_params() {
+        let enc_key = [0x00, 0x04, 0x01, 0x02];
+        assert!(matches!(u8_to_key(&enc_key), Err(Error::Crypto(_))));
+    }
 }
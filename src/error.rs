@@ -0,0 +1,68 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The server rejected the credentials or the second factor.
+    #[error("Authentication failed")]
+    Authentication,
+    #[error("Client needs to be authenticated first")]
+    NotAuthenticated,
+    /// The access token is no longer accepted by the server, or the session timed out.
+    #[error("Session expired")]
+    SessionExpired,
+    /// The server rate limited the client without telling it when to retry.
+    #[error("Rate limited by the server")]
+    RateLimited,
+    #[error("Server responded with status {0}")]
+    Status(StatusCode),
+    #[error("Request failed: {0}")]
+    Http(#[source] reqwest::Error),
+    /// A response did not match the expected schema.
+    #[error("Could not deserialize response: {0}")]
+    Deserialization(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Message could not be verified")]
+    InvalidMac,
+    #[error("Decryption failed: {0}")]
+    Crypto(String),
+    #[error("Missing data: {0}")]
+    MissingData(String),
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+    #[error("Websocket connection closed, needs to be reconnected")]
+    WebSocketClosed,
+    #[error("Websocket error: {0}")]
+    WebSocket(#[source] Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The operation needs the server, but the client runs in offline mode.
+    #[error("Not available in offline mode")]
+    Offline,
+    #[error("Invalid url: {0}")]
+    Url(#[from] url::ParseError),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Error::Deserialization(Box::new(e))
+        } else {
+            Error::Http(e)
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Deserialization(Box::new(e))
+    }
+}
@@ -1,29 +1,56 @@
-use anyhow::{bail, Context, Result};
+use crate::config::Endpoint;
+use crate::Error;
+use crate::Result;
 use reqwest::{header::HeaderMap, Client, Response, StatusCode};
+use std::sync::Mutex;
 use std::time::Duration;
-use tracing::warn;
+use tokio::time::Instant;
+use tracing::{debug, warn};
 use url::Url;
 
+/// Non-standard status the server responds with once a session timed out.
+pub(crate) const SESSION_EXPIRED: u16 = 440;
+
 #[derive(Clone)]
 pub enum Method {
     Get,
     Post,
     AuthGet,
+    AuthPost,
     AuthPut,
+    AuthDelete,
 }
 
 pub struct HttpClient {
     client: Client,
+    endpoint: Endpoint,
     access_token: Option<String>,
+    /// Set while the server rate limits us, so concurrent requests wait as well.
+    retry_at: Mutex<Option<Instant>>,
 }
 
 impl HttpClient {
-    pub fn new() -> HttpClient {
+    pub fn new(endpoint: Endpoint) -> HttpClient {
         let client = reqwest::Client::new();
         HttpClient {
             client,
+            endpoint,
             access_token: None,
+            retry_at: Mutex::new(None),
+        }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Resolves `path` relative to the base url, keeping any path prefix of the base url.
+    pub fn url(&self, path: &str) -> Result<Url> {
+        let mut base = self.endpoint.base_url.clone();
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
         }
+        Ok(base.join(path.trim_start_matches('/'))?)
     }
 
     pub fn set_access_token(&mut self, access_token: String) {
@@ -32,6 +59,9 @@ impl HttpClient {
     pub fn get_access_token(&self) -> Option<&String> {
         self.access_token.as_ref()
     }
+    pub fn clear_access_token(&mut self) {
+        self.access_token = None;
+    }
 
     fn get_access_token_header(&self) -> Result<HeaderMap> {
         let mut request_headers = HeaderMap::new();
@@ -39,8 +69,9 @@ impl HttpClient {
             "accessToken",
             self.access_token
                 .clone()
-                .context("Client needs to be authenticated first")?
-                .parse()?,
+                .ok_or(Error::NotAuthenticated)?
+                .parse()
+                .map_err(|_| Error::InvalidData("Access token is not a valid header".into()))?,
         );
         Ok(request_headers)
     }
@@ -48,6 +79,7 @@ impl HttpClient {
     pub async fn send(&self, method: Method, url: Url, body: Option<String>) -> Result<Response> {
         let request_method: reqwest::Method;
         let mut request_headers = HeaderMap::new();
+        let authenticated = !matches!(method, Method::Get | Method::Post);
         match method {
             Method::Get => {
                 request_method = reqwest::Method::GET;
@@ -59,13 +91,22 @@ impl HttpClient {
                 request_method = reqwest::Method::GET;
                 request_headers = self.get_access_token_header()?;
             }
+            Method::AuthPost => {
+                request_method = reqwest::Method::POST;
+                request_headers = self.get_access_token_header()?;
+            }
             Method::AuthPut => {
                 request_method = reqwest::Method::PUT;
                 request_headers = self.get_access_token_header()?;
             }
+            Method::AuthDelete => {
+                request_method = reqwest::Method::DELETE;
+                request_headers = self.get_access_token_header()?;
+            }
         };
 
         loop {
+            self.wait_for_rate_limit().await;
             let mut curr_request = self
                 .client
                 .request(request_method.clone(), url.clone())
@@ -75,23 +116,46 @@ impl HttpClient {
             }
             let curr_request = curr_request.build()?;
             let response = self.client.execute(curr_request).await?;
-            let headers = response.headers().clone();
-            match response.error_for_status() {
-                Err(e) => {
-                    if e.status() == Some(StatusCode::TOO_MANY_REQUESTS) {
-                        if let Some(duration) = Self::get_retry_duration(&headers) {
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            match status {
+                StatusCode::TOO_MANY_REQUESTS => {
+                    match Self::get_retry_duration(response.headers()) {
+                        Some(duration) => {
                             warn!("Rate limited, retrying in {} seconds", duration.as_secs());
-                            tokio::time::sleep(duration).await;
+                            self.set_retry_at(Instant::now() + duration);
                         }
-                    } else {
-                        bail!(e.to_string());
+                        None => return Err(Error::RateLimited),
                     }
                 }
-                Ok(res) => return Ok(res),
+                StatusCode::UNAUTHORIZED if authenticated => return Err(Error::SessionExpired),
+                StatusCode::UNAUTHORIZED => return Err(Error::Authentication),
+                _ if status.as_u16() == SESSION_EXPIRED => return Err(Error::SessionExpired),
+                _ => return Err(Error::Status(status)),
+            }
+        }
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let retry_at = *self.retry_at.lock().unwrap();
+        if let Some(retry_at) = retry_at {
+            if retry_at > Instant::now() {
+                debug!("Waiting for rate limit");
+                tokio::time::sleep_until(retry_at).await;
             }
         }
     }
 
+    fn set_retry_at(&self, instant: Instant) {
+        let mut retry_at = self.retry_at.lock().unwrap();
+        if retry_at.is_none_or(|at| at < instant) {
+            *retry_at = Some(instant);
+        }
+    }
+
     fn get_retry_duration(header_map: &HeaderMap) -> Option<Duration> {
         if let Some(val) = header_map.get("Retry-After") {
             if let Ok(retry_value) = val.to_str() {
@@ -13,14 +13,38 @@
 //!     "...@tuta.com",
 //!     "some_password",
 //!     watch_spam: true,
+//!     watch_folders: Vec::new(),
 //!     show_name: true,
 //!     show_subject: true,
 //!     show_body: true,
+//!     show_headers: false,
+//!     totp_secret: None,
+//!     endpoint: Default::default(),
+//!     cache_dir: None,
 //! };
 //!
 //! let client = tuta_poll::client::Client::new(&config).await?;
 //! ```
 //!
+//! ### Resume a session
+//!
+//! ```ignore
+//! let saved = client.save_session()?;
+//! // persist `saved`, it implements `Serialize` and `Deserialize`
+//!
+//! let client = tuta_poll::client::Client::resume(&config, &saved).await?;
+//! ```
+//!
+//! ### Offline cache
+//!
+//! ```ignore
+//! // mails and their bodies are cached encrypted in `cache_dir` while online
+//! config.cache_dir = Some("/path/to/cache".into());
+//!
+//! // later, without network access
+//! let client = tuta_poll::client::Client::offline(&config).await?;
+//! ```
+//!
 //! ### Get messages
 //! ```ignore
 //! use futures_util::pin_mut;
@@ -33,18 +57,88 @@
 //!     let decrypted_mail = client.decrypt(&mail).await;
 //! }
 //! ```
+//!
+//! Decrypt many mails at once, e.g. to catch up after downtime:
+//! ```ignore
+//! let decrypted = client.decrypt_stream(client.get_mails(), 8);
+//! pin_mut!(decrypted);
+//! while let Some(decrypted) = decrypted.next().await {
+//!     let (mail, decrypted_mail) = decrypted?;
+//! }
+//! ```
+//! ### Folders
+//!
+//! ```ignore
+//! for folder in client.folders() {
+//!     println!("{:?} {}", folder.folder_type, client.folder_path(folder));
+//! }
+//!
+//! // watch custom folders instead of Inbox and Spam, either in the config
+//! config.watch_folders = vec!["Alerts/Prod".into()];
+//! // or after logging in
+//! client.watch_folders(|folder| folder.name == "Alerts").await?;
+//! ```
+//!
+//! ### Move and delete mails
+//!
+//! ```ignore
+//! let archive = client.folder_by_path("Archive").unwrap();
+//! let result = client.move_mails(&ids, archive).await?;
+//! for (id, e) in result.failed {
+//!     println!("Could not move {:?}: {}", id, e);
+//! }
+//!
+//! client.set_read_status_batch(&ids, ReadStatus::Read).await?;
+//!
+//! // mails are deleted in two steps, just like in the web app
+//! client.trash_mails(&ids).await?;
+//! client.delete_mails_permanently(&ids).await?;
+//! ```
+//!
+//! ### Attachments
+//!
+//! ```ignore
+//! for attachment in client.attachments(&mail).await? {
+//!     if attachment.mime_type == "application/pdf" {
+//!         let mut file = tokio::fs::File::create(&attachment.name).await?;
+//!         tokio::io::copy(&mut client.attachment_reader(&attachment), &mut file).await?;
+//!     }
+//! }
+//! ```
+//!
+//! ### Labels
+//!
+//! ```ignore
+//! let ack = client.create_label("ack", "#2196f3").await?;
+//! client.apply_labels(&ids, &[ack.clone()]).await?;
+//! client.remove_labels(&ids, &[ack]).await?;
+//! ```
+//!
+//! ### Incremental sync
+//!
+//! ```ignore
+//! // persist `state` between polls, it implements `Serialize` and `Deserialize`
+//! let mut state = tuta_poll::types::SyncState::default();
+//!
+//! for change in client.sync(&mut state).await? {
+//!     match change {
+//!         MailChange::Created(mail) => {}
+//!         MailChange::Updated(mail) => {}
+//!         MailChange::Deleted(id) => {}
+//!     }
+//! }
+//! ```
+//!
 //! ### Connect to websocket
 //!
 //! ```ignore
-//! let connector = client.get_websocket_connector()?;
+//! // reconnects on its own and replays updates missed in the meantime
+//! let events = client.events();
+//! pin_mut!(events);
 //!
-//! loop {
-//!     let mut socket = connector.connect()?;
-//!     while let Ok(has_new) = socket.has_new().await {
-//!         if !has_new {
-//!             continue;
-//!         }
-//!         let mails = client.get_mails();
+//! while let Some(event) = events.next().await {
+//!     if let EntityEvent::Mail { operation: OperationType::Create, id } = event? {
+//!         let mail = client.get_mail(&id).await?;
 //!     }
 //! }
 //! ```
@@ -55,6 +149,10 @@ pub mod config;
 pub mod crypto;
 pub mod types;
 
+pub use error::{Error, Result};
+
+mod cache;
+mod error;
 mod http_client;
 mod serialize;
 mod websocket;
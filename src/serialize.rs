@@ -60,12 +60,12 @@ pub mod serde_base64_16 {
         }
     }
 
-    // pub fn serialize<S: serde::Serializer>(
-    //     value: &[u8; 16],
-    //     serializer: S,
-    // ) -> Result<S::Ok, S::Error> {
-    //     serializer.serialize_str(&engines::STANDARD.encode(value))
-    // }
+    pub fn serialize<S: serde::Serializer>(
+        value: &[u8; 16],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&engines::STANDARD.encode(value))
+    }
 }
 
 pub mod serde_option_base64_16 {
@@ -223,3 +223,18 @@ pub mod string_to_enum {
         serializer.serialize_str(&value.clone().into().to_string())
     }
 }
+
+pub mod string_to_number {
+    use std::str::FromStr;
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        D: serde::Deserializer<'de>,
+    {
+        use serde::Deserialize;
+        let s = String::deserialize(deserializer)?;
+        s.parse::<T>()
+            .map_err(|_| serde::de::Error::custom("Expected a number wrapped in a string"))
+    }
+}
@@ -8,14 +8,50 @@ pub type Id = String;
 pub type IdTuple = (Id, Id);
 pub type Base64 = Vec<u8>;
 pub type Aes128Key = [u8; 16];
+pub type Aes256Key = [u8; 32];
 
 #[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Session {
     #[serde(with = "serde_format")]
     _format: (),
     #[serde(rename = "accessToken")]
     pub access_token: String,
     pub user: Id,
+    #[serde(default)]
+    pub challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    #[serde(with = "string_to_enum", rename = "type")]
+    pub challenge_type: ChallengeType,
+}
+
+#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone)]
+#[repr(u8)]
+pub enum ChallengeType {
+    U2f,
+    Otp,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Salt {
+    #[serde(with = "serde_base64_16")]
+    pub salt: Aes128Key,
+    #[serde(with = "string_to_enum")]
+    pub kdf_version: KdfType,
+}
+
+/// Key derivation function used to derive the passphrase key of an account, sent by the server
+/// as `kdfVersion`.
+#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone, Default)]
+#[repr(u8)]
+pub enum KdfType {
+    #[default]
+    Bcrypt,
+    Argon2id,
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -33,17 +69,26 @@ pub struct User {
 }
 
 impl User {
+    /// A user without memberships or keys, for clients which never talk to the server.
+    pub(crate) fn offline(id: Id) -> User {
+        User {
+            id,
+            ..Default::default()
+        }
+    }
+
     pub fn has_group(&self, group_id: &Id) -> bool {
-        return self.user_group.group == *group_id
-            || self.memberships.iter().any(|m| m.group == *group_id);
+        self.user_group.group == *group_id || self.memberships.iter().any(|m| m.group == *group_id)
     }
 
-    pub fn unlock_group_keys(&mut self, user_passphrase_key: &Aes128Key) {
-        let user_group_key =
-            crypto::decrypt_key(&user_passphrase_key, &self.user_group.sym_enc_g_key);
+    pub fn unlock_group_keys(
+        &mut self,
+        user_passphrase_key: &crypto::PassphraseKey,
+    ) -> crate::Result<()> {
+        let user_group_key = user_passphrase_key.decrypt_key(&self.user_group.sym_enc_g_key)?;
 
         self.group_keys
-            .insert(self.user_group.group.clone(), user_group_key.clone());
+            .insert(self.user_group.group.clone(), user_group_key);
 
         for member in &self.memberships {
             if let Some(sym) = member.sym_enc_g_key {
@@ -53,17 +98,41 @@ impl User {
                 );
             }
         }
+        Ok(())
+    }
+
+    /// Returns all unlocked group keys, each encrypted with `key`.
+    pub fn export_group_keys(&self, key: &Aes128Key) -> Vec<EncryptedGroupKey> {
+        self.group_keys
+            .iter()
+            .map(|(group, group_key)| EncryptedGroupKey {
+                group: group.clone(),
+                key: crypto::encrypt_key(key, group_key),
+            })
+            .collect()
+    }
+
+    /// Unlocks group keys previously returned by [`User::export_group_keys`].
+    pub fn import_group_keys(&mut self, key: &Aes128Key, group_keys: &[EncryptedGroupKey]) {
+        for group_key in group_keys {
+            self.group_keys.insert(
+                group_key.group.clone(),
+                crypto::decrypt_key(key, &group_key.key),
+            );
+        }
+    }
+
+    /// Ids of the user group and every group the user is a member of.
+    pub fn group_ids(&self) -> impl Iterator<Item = &Id> {
+        std::iter::once(&self.user_group.group).chain(self.memberships.iter().map(|m| &m.group))
     }
 
     pub fn get_group_key(&self, group_id: &Id) -> Option<Aes128Key> {
         self.group_keys.get(group_id).copied()
     }
 
-    pub fn get_user_group_key(&self) -> Aes128Key {
-        self.group_keys
-            .get(&self.user_group.group)
-            .copied()
-            .unwrap()
+    pub fn get_user_group_key(&self) -> Option<Aes128Key> {
+        self.group_keys.get(&self.user_group.group).copied()
     }
 }
 
@@ -82,8 +151,10 @@ pub struct Membership {
 pub struct UserGroup {
     pub group: Id,
     pub group_info: IdTuple,
-    #[serde(with = "serde_base64_16")]
-    pub sym_enc_g_key: Aes128Key,
+    /// Encrypted with the passphrase key, 16 bytes for bcrypt and 65 bytes (IV and MAC) for
+    /// Argon2id accounts.
+    #[serde(with = "serde_base64")]
+    pub sym_enc_g_key: Base64,
 }
 
 pub struct Credentials {
@@ -92,6 +163,28 @@ pub struct Credentials {
     pub user_id: Id,
 }
 
+/// Everything needed to resume a session without logging in again.
+///
+/// The group keys are encrypted with the access key of the session, which the server only hands
+/// out to holders of a valid access token.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSession {
+    pub access_token: String,
+    pub user_id: Id,
+    pub group_keys: Vec<EncryptedGroupKey>,
+    /// Key of the mail cache, encrypted with the access key.
+    #[serde(default, with = "serde_option_base64_16")]
+    pub cache_key: Option<Aes128Key>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EncryptedGroupKey {
+    pub group: Id,
+    #[serde(with = "serde_base64_16")]
+    pub key: Aes128Key,
+}
+
 #[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone)]
 #[repr(u8)]
 pub enum GroupType {
@@ -129,9 +222,29 @@ pub enum MailFolderType {
     Archive,
     Spam,
     Draft,
+    All,
+    Label,
+}
+
+impl MailFolderType {
+    /// Name shown for system folders, which have no name of their own.
+    pub fn default_name(&self) -> &'static str {
+        match self {
+            MailFolderType::Custom => "",
+            MailFolderType::Inbox => "Inbox",
+            MailFolderType::Sent => "Sent",
+            MailFolderType::Trash => "Trash",
+            MailFolderType::Archive => "Archive",
+            MailFolderType::Spam => "Spam",
+            MailFolderType::Draft => "Draft",
+            MailFolderType::All => "All",
+            MailFolderType::Label => "",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Folder {
     #[serde(with = "serde_format")]
     _format: (),
@@ -144,10 +257,40 @@ pub struct Folder {
     pub name: Base64,
     #[serde(with = "serde_base64_16", rename = "_ownerEncSessionKey")]
     pub owner_enc_session_key: Aes128Key,
+    #[serde(rename = "_ownerGroup")]
+    pub owner_group: Id,
+    #[serde(rename = "parentFolder", default)]
+    pub parent_folder: Option<IdTuple>,
+    /// Encrypted color, only set for labels.
+    #[serde(with = "serde_option_base64", default)]
+    pub color: Option<Base64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A label which can be applied to mails, with its decrypted name and color.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub id: IdTuple,
+    pub name: String,
+    /// Color as hex string, e.g. `#2196f3`.
+    pub color: String,
+}
+
+/// A mail folder with its decrypted name.
+#[derive(Debug, Clone)]
+pub struct MailFolder {
+    pub id: IdTuple,
+    pub folder_type: MailFolderType,
+    /// Name of the folder, system folders are named after their type, e.g. `Inbox`.
+    pub name: String,
+    /// List id of the mails in the folder.
+    pub mails: Id,
+    /// Id of the folder containing this folder, `None` for top level folders.
+    pub parent: Option<IdTuple>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Mail {
     #[serde(with = "serde_format", rename = "_format")]
     format: (),
@@ -155,21 +298,26 @@ pub struct Mail {
     pub attachments: Vec<(String, String)>,
     // was not needed yet
     pub bucket_key: (),
-    pub body: String,
+    /// Legacy mail body, newer mails store it in the mail details instead.
+    pub body: Option<Id>,
+    #[serde(default)]
     pub bcc_recipients: Vec<Sender>,
+    #[serde(default)]
     pub cc_recipients: Vec<Sender>,
     #[serde(with = "serde_base64")]
     pub confidential: Base64,
     pub conversation_entry: (String, String),
     pub different_envelope_sender: Option<String>,
     pub first_recipient: Sender,
-    pub headers: Option<String>,
+    /// Id of the legacy [`EncryptedHeaders`], newer mails store them in the mail details.
+    pub headers: Option<Id>,
     #[serde(rename = "_id")]
     pub id: (String, String),
     #[serde(with = "serde_base64")]
     pub list_unsubscribe: Base64,
-    pub mail_details: (),
-    pub mail_details_draft: (),
+    /// Id of the [`MailDetailsBlob`] in the blob store, set for mails on the newer model.
+    pub mail_details: Option<IdTuple>,
+    pub mail_details_draft: Option<IdTuple>,
     #[serde(with = "serde_base64")]
     pub method: Base64,
     pub moved_time: String,
@@ -182,6 +330,7 @@ pub struct Mail {
     pub phishing_status: String,
     pub received_date: String,
     pub recipient_count: String,
+    #[serde(default)]
     pub reply_tos: Vec<Sender>,
     pub reply_type: String,
     pub sent_date: String,
@@ -189,12 +338,204 @@ pub struct Mail {
     pub state: String,
     #[serde(with = "serde_base64")]
     pub subject: Base64,
+    #[serde(default)]
     pub to_recipients: Vec<Sender>,
     #[serde(with = "string_to_enum", rename = "unread")]
     pub read_status: ReadStatus,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Body, recipients and headers of a mail, which newer mails store in the blob store.
+#[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
+pub struct MailDetailsBlob {
+    #[serde(with = "serde_format")]
+    _format: (),
+    #[serde(rename = "_id")]
+    pub id: IdTuple,
+    pub details: MailDetails,
+}
+
+/// Body, recipients and headers of a draft, which are not stored in the blob store.
+#[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
+pub struct MailDetailsDraft {
+    #[serde(with = "serde_format")]
+    _format: (),
+    #[serde(rename = "_id")]
+    pub id: IdTuple,
+    pub details: MailDetails,
+}
+
+/// Still encrypted with the session key of the mail.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MailDetails {
+    pub sent_date: String,
+    pub body: Body,
+    pub recipients: Recipients,
+    #[serde(default)]
+    pub reply_tos: Vec<EncryptedMailAddress>,
+    pub headers: Option<EncryptedHeaders>,
+}
+
+/// Raw headers of a mail, either `headers` or `compressed_headers` is set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedHeaders {
+    #[serde(with = "serde_option_base64", default)]
+    pub headers: Option<Base64>,
+    #[serde(with = "serde_option_base64", default)]
+    pub compressed_headers: Option<Base64>,
+}
+
+/// Parsed mail headers, which may contain the same name several times, e.g. `Received`.
+///
+/// Names are matched case-insensitively, the headers keep the order of the mail.
+#[derive(Debug, Clone, Default)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Parses raw headers, folded lines are joined.
+    pub fn parse(raw: &str) -> Headers {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for line in raw.lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = entries.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                entries.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        Headers { entries }
+    }
+
+    /// Returns the first value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all values of `name`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// Either `text` or `compressed_text` is set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Body {
+    #[serde(with = "serde_option_base64", default)]
+    pub text: Option<Base64>,
+    #[serde(with = "serde_option_base64", default)]
+    pub compressed_text: Option<Base64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Recipients {
+    #[serde(default)]
+    pub to_recipients: Vec<Sender>,
+    #[serde(default)]
+    pub cc_recipients: Vec<Sender>,
+    #[serde(default)]
+    pub bcc_recipients: Vec<Sender>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EncryptedMailAddress {
+    pub address: String,
+    #[serde(with = "serde_base64")]
+    pub name: Base64,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(clippy::manual_non_exhaustive)]
+pub struct File {
+    #[serde(with = "serde_format")]
+    _format: (),
+    #[serde(rename = "_id")]
+    pub id: IdTuple,
+    #[serde(
+        with = "serde_option_base64_16",
+        rename = "_ownerEncSessionKey",
+        default
+    )]
+    pub owner_enc_session_key: Option<Aes128Key>,
+    #[serde(rename = "_ownerGroup")]
+    pub owner_group: Id,
+    #[serde(rename = "_permissions")]
+    pub permissions: Id,
+    #[serde(with = "serde_base64")]
+    pub name: Base64,
+    #[serde(with = "serde_option_base64", rename = "mimeType", default)]
+    pub mime_type: Option<Base64>,
+    #[serde(with = "string_to_number")]
+    pub size: u64,
+    #[serde(default)]
+    pub blobs: Vec<Blob>,
+}
+
+/// Reference to a part of the data of an entity in the blob store.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    pub archive_id: Id,
+    pub blob_id: Id,
+    #[serde(with = "string_to_number")]
+    pub size: u64,
+}
+
+/// Kind of data stored in a blob archive, needed to request access to it.
+#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone, Copy)]
+#[repr(u8)]
+pub enum ArchiveDataType {
+    AuthorityRequests,
+    Attachments,
+    MailDetails,
+}
+
+/// An attachment of a mail with its decrypted metadata.
+#[derive(Clone)]
+pub struct Attachment {
+    pub id: IdTuple,
+    pub name: String,
+    pub mime_type: String,
+    /// Size of the decrypted data in bytes.
+    pub size: u64,
+    pub(crate) session_key: Aes128Key,
+    pub(crate) blobs: Vec<Blob>,
+}
+
+impl std::fmt::Debug for Attachment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Attachment")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("mime_type", &self.mime_type)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Sender {
     pub address: String,
@@ -205,6 +546,27 @@ pub struct Sender {
     pub name: Base64,
 }
 
+/// Position of the incremental sync, i.e. the last processed entity event batch of each group.
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub last_event_ids: HashMap<Id, Id>,
+}
+
+/// Outcome of an operation on several mails, which may fail for single mails only.
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    pub succeeded: Vec<IdTuple>,
+    pub failed: Vec<(IdTuple, crate::Error)>,
+}
+
+#[derive(Debug)]
+pub enum MailChange {
+    Created(Mail),
+    Updated(Mail),
+    Deleted(IdTuple),
+}
+
 #[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone)]
 #[repr(u8)]
 pub enum ReadStatus {
@@ -214,6 +576,7 @@ pub enum ReadStatus {
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Permission {
     #[serde(with = "serde_format", rename = "_format")]
     _format: (),
@@ -221,7 +584,7 @@ pub struct Permission {
     pub permission_type: PermissionType,
     #[serde(with = "serde_option_base64_16")]
     pub bucket_enc_session_key: Option<Aes128Key>,
-    #[serde(rename = "_ownerEncSessionKey")]
+    #[serde(with = "serde_option_base64_16", rename = "_ownerEncSessionKey")]
     pub owner_enc_session_key: Option<Aes128Key>,
     #[serde(rename = "_ownerGroup")]
     pub owner_group: Option<Id>,
@@ -247,6 +610,7 @@ pub struct Bucket {
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct BucketPermission {
     #[serde(with = "serde_format", rename = "_format")]
     _format: (),
@@ -288,12 +652,94 @@ pub struct KeyPair {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EntityUpdate {
+    pub event_batch_id: Id,
+    pub event_batch_owner: Id,
     pub event_batch: Vec<Event>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct EntityEventBatch {
+    #[serde(rename = "_id")]
+    pub id: IdTuple,
+    pub events: Vec<Event>,
+}
+
+impl From<EntityEventBatch> for EntityUpdate {
+    fn from(batch: EntityEventBatch) -> Self {
+        let (event_batch_owner, event_batch_id) = batch.id;
+        EntityUpdate {
+            event_batch_id,
+            event_batch_owner,
+            event_batch: batch.events,
+        }
+    }
+}
+
+/// Message received on the event websocket.
+#[derive(Debug)]
+pub enum WebSocketMessage {
+    EntityUpdate(EntityUpdate),
+    UnreadCounterUpdate(UnreadCounterUpdate),
+    LeaderStatus(LeaderStatus),
+    PhishingMarkers(PhishingMarkers),
+}
+
+/// Unread counts of the mail lists of a mail group.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadCounterUpdate {
+    pub mail_group: Id,
+    pub counter_values: Vec<CounterValue>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct CounterValue {
+    /// Id of the mail list the counter belongs to, i.e. [`Folder::mails`].
+    pub counter_id: Id,
+    #[serde(with = "string_to_number")]
+    pub count: u64,
+}
+
+/// Whether this client is the leader, i.e. the one client of the user which may write.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderStatus {
+    pub leader_status: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhishingMarkers {
+    pub last_id: Id,
+    pub markers: Vec<PhishingMarker>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PhishingMarker {
+    pub marker: String,
+    #[serde(with = "string_to_enum")]
+    pub status: PhishingMarkerStatus,
+}
+
+#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone)]
+#[repr(u8)]
+pub enum PhishingMarkerStatus {
+    Active,
+    Inactive,
+}
+
+impl EntityUpdate {
+    pub fn events(&self) -> impl Iterator<Item = EntityEvent> + '_ {
+        self.event_batch.iter().cloned().map(EntityEvent::from)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct Event {
+    #[serde(default)]
+    pub application: String,
     pub instance_id: String,
     pub instance_list_id: String,
     #[serde(with = "string_to_enum")]
@@ -302,10 +748,104 @@ pub struct Event {
     pub event_type: String, // yes this is really a string
 }
 
-#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone)]
+/// A single entity update, typed for the entities this crate knows about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityEvent {
+    /// `id` can be passed to [`crate::client::Client::get_mail`] for created and updated mails.
+    Mail {
+        operation: OperationType,
+        id: IdTuple,
+    },
+    MailFolder {
+        operation: OperationType,
+        id: IdTuple,
+    },
+    Other(Event),
+}
+
+impl From<Event> for EntityEvent {
+    fn from(event: Event) -> Self {
+        let id = (event.instance_list_id.clone(), event.instance_id.clone());
+        match event.event_type.as_str() {
+            "Mail" => EntityEvent::Mail {
+                operation: event.operation,
+                id,
+            },
+            "MailFolder" => EntityEvent::MailFolder {
+                operation: event.operation,
+                id,
+            },
+            _ => EntityEvent::Other(event),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, TryFromPrimitive, IntoPrimitive, Clone, Copy)]
 #[repr(u8)]
 pub enum OperationType {
     Create,
     Update,
     Delete,
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A mail of the list `mails` with empty encrypted fields and no session key.
+    pub(crate) fn mail(mails: &str, id: &str) -> Mail {
+        let sender = serde_json::json!({
+            "address": "sender@tuta.com",
+            "contact": null,
+            "_id": "sender",
+            "name": "",
+        });
+        serde_json::from_value(serde_json::json!({
+            "_format": "0",
+            "authStatus": "0",
+            "attachments": [],
+            "bucketKey": null,
+            "body": null,
+            "confidential": "",
+            "conversationEntry": ["conversation", id],
+            "differentEnvelopeSender": null,
+            "firstRecipient": sender,
+            "headers": null,
+            "_id": [mails, id],
+            "listUnsubscribe": "",
+            "mailDetails": null,
+            "mailDetailsDraft": null,
+            "method": "",
+            "movedTime": "0",
+            "_ownerEncSessionKey": null,
+            "_ownerGroup": "group",
+            "_permissions": "permissions",
+            "phishingStatus": "0",
+            "receivedDate": "0",
+            "recipientCount": "1",
+            "replyType": "0",
+            "sentDate": "0",
+            "sender": sender,
+            "state": "2",
+            "subject": "",
+            "unread": "1",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_headers() {
+        let headers = Headers::parse(
+            "Received: from a.example\r\n\tby b.example\r\nreceived: from c.example\r\nSubject: Hello\r\n  world\r\nX-Empty:\r\n",
+        );
+        assert_eq!(
+            headers.get_all("Received").collect::<Vec<_>>(),
+            ["from a.example by b.example", "from c.example"]
+        );
+        assert_eq!(headers.get("subject"), Some("Hello world"));
+        assert_eq!(headers.get("SUBJECT"), Some("Hello world"));
+        assert_eq!(headers.get("x-empty"), Some(""));
+        assert_eq!(headers.get("From"), None);
+        assert_eq!(headers.iter().count(), 4);
+    }
+}
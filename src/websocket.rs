@@ -1,39 +1,74 @@
-use crate::http_client::HttpClient;
-use crate::types::{EntityUpdate, OperationType};
-use anyhow::{anyhow, bail, Context, Result};
-use std::net::TcpStream;
+use crate::http_client::{HttpClient, SESSION_EXPIRED};
+use crate::types::{EntityEvent, OperationType, WebSocketMessage};
+use crate::{Error, Result};
+use futures_core::stream::Stream;
+use futures_util::StreamExt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, http::StatusCode, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use tracing::debug;
-use tungstenite::Message;
-use tungstenite::{protocol::WebSocket as TWebSocket, stream::MaybeTlsStream};
+
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
 
 pub struct WebSocketConnector {
     url: url::Url,
 }
 
+/// Event socket of the server, yields every message it receives.
+///
+/// The stream ends after yielding [`Error::WebSocketClosed`] once the connection is closed.
+/// Dropping it (e.g. in a `tokio::select!` branch) cancels any pending read.
 pub struct WebSocket {
-    socket: TWebSocket<MaybeTlsStream<TcpStream>>,
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    closed: bool,
 }
 
 impl WebSocketConnector {
     pub fn from_url(client: &HttpClient, user_id: &str) -> Result<WebSocketConnector> {
-        let mut url = url::Url::parse(crate::api::BASE_URL)?.join("event")?;
-        url.set_scheme("wss")
-            .map_err(|e| anyhow!("Could not set scheme to wss with error {:?}", e))?;
+        let endpoint = client.endpoint();
+        let mut url = client.url("event")?;
+        let scheme = match url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            other => {
+                return Err(Error::InvalidData(format!(
+                    "Unsupported base url scheme {}",
+                    other
+                )))
+            }
+        };
+        url.set_scheme(scheme)
+            .map_err(|_| Error::InvalidData(format!("Could not set scheme to {}", scheme)))?;
         url.query_pairs_mut()
-            .append_pair("modelVersions", &crate::api::MODEL_VERSION)
-            .append_pair("clientVersion", &crate::api::CLIENT_VERSION)
+            .append_pair("modelVersions", &endpoint.model_version)
+            .append_pair("clientVersion", &endpoint.client_version)
             .append_pair("userId", user_id)
             .append_pair(
                 "accessToken",
-                &client
-                    .get_access_token()
-                    .context("Client must be authenticated first")?,
+                client.get_access_token().ok_or(Error::NotAuthenticated)?,
             );
         Ok(WebSocketConnector { url })
     }
 
-    pub fn connect(&self) -> Result<WebSocket> {
-        let (socket, response) = tungstenite::connect(&self.url)?;
+    /// Opens the socket, a handshake rejected because of the access token fails with
+    /// [`Error::SessionExpired`].
+    pub async fn connect(&self) -> Result<WebSocket> {
+        let (socket, response) =
+            tokio_tungstenite::connect_async(&self.url)
+                .await
+                .map_err(|e| match e {
+                    tungstenite::Error::Http(ref response)
+                        if response.status() == StatusCode::UNAUTHORIZED
+                            || response.status().as_u16() == SESSION_EXPIRED =>
+                    {
+                        Error::SessionExpired
+                    }
+                    e => e.into(),
+                })?;
         debug!("Connected to the server");
         debug!("Response HTTP code: {}", response.status());
         debug!("Response contains the following headers:");
@@ -41,59 +76,241 @@ impl WebSocketConnector {
             debug!("* {}", header);
         }
 
-        Ok(WebSocket { socket })
+        Ok(WebSocket {
+            socket,
+            closed: false,
+        })
     }
 }
 
 impl WebSocket {
     pub async fn has_new(&mut self) -> Result<bool> {
-        let update = self.read_all()?;
-        let events: Vec<_> = update
-            .event_batch
-            .iter()
-            .filter(|b| b.operation == OperationType::Create)
-            .filter(|b| b.event_type == "Mail")
-            .collect();
-
-        if events.is_empty() {
-            Ok(false)
-        } else {
-            Ok(true)
-        }
+        let message = self.next().await.ok_or(Error::WebSocketClosed)??;
+        let WebSocketMessage::EntityUpdate(update) = message else {
+            return Ok(false);
+        };
+        let has_new = update.events().any(|e| {
+            matches!(
+                e,
+                EntityEvent::Mail {
+                    operation: OperationType::Create,
+                    ..
+                }
+            )
+        });
+
+        Ok(has_new)
     }
-    fn read_all(&mut self) -> Result<EntityUpdate> {
+
+    fn parse(text: &str) -> Option<Result<WebSocketMessage>> {
+        let (kind, payload) = text.split_once(';')?;
+        let message = match kind {
+            "entityUpdate" => serde_json::from_str(payload).map(WebSocketMessage::EntityUpdate),
+            "unreadCounterUpdate" => {
+                serde_json::from_str(payload).map(WebSocketMessage::UnreadCounterUpdate)
+            }
+            "leaderStatus" => serde_json::from_str(payload).map(WebSocketMessage::LeaderStatus),
+            "phishingMarkers" => {
+                serde_json::from_str(payload).map(WebSocketMessage::PhishingMarkers)
+            }
+            _ => {
+                debug!("Received ignored response: {}", kind);
+                return None;
+            }
+        };
+        debug!("Handle {} request", kind);
+        Some(message.map_err(Error::from))
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<WebSocketMessage>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.closed {
+            return Poll::Ready(None);
+        }
+
         loop {
-            if let Ok(msg) = self.socket.read() {
-                match msg {
-                    Message::Text(text) => {
-                        if let Some((a, b)) = text.split_once(";") {
-                            match a {
-                                "entityUpdate" => {
-                                    debug!("Handle {} request", a);
-                                    return Ok(serde_json::from_str(b)?);
-                                }
-                                _ => debug!("Received ignored response: {}", a),
-                            }
-                        }
-                    }
-                    Message::Binary(_) => debug!("Got binary reponse"),
-                    Message::Ping(data) => {
-                        debug!("Got ping, answering with pong");
-                        self.socket.write(Message::Pong(data))?;
-                    }
-                    Message::Pong(_) => debug!("Got pong"),
-                    Message::Close(close_frame) => {
-                        debug!("Got close {:?}", close_frame);
-                        bail!("Connection closed, needs to be reconnected");
+            let msg = match self.socket.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(msg))) => msg,
+                Poll::Ready(Some(Err(e))) => {
+                    self.closed = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(None) => {
+                    self.closed = true;
+                    return Poll::Ready(Some(Err(Error::WebSocketClosed)));
+                }
+            };
+
+            // pings are answered by tungstenite on the next read
+            match msg {
+                Message::Text(text) => {
+                    if let Some(message) = Self::parse(&text) {
+                        return Poll::Ready(Some(message));
                     }
-                    Message::Frame(_) => debug!("Got frame"),
                 }
+                Message::Binary(_) => debug!("Got binary reponse"),
+                Message::Ping(_) => debug!("Got ping"),
+                Message::Pong(_) => debug!("Got pong"),
+                Message::Close(close_frame) => {
+                    debug!("Got close {:?}", close_frame);
+                    self.closed = true;
+                    return Poll::Ready(Some(Err(Error::WebSocketClosed)));
+                }
+                Message::Frame(_) => debug!("Got frame"),
             }
         }
     }
+}
 
-    pub fn close(&mut self) -> Result<()> {
-        self.socket.close(None)?;
-        Ok(())
+/// Jittered exponential backoff between reconnect attempts.
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Backoff {
+        Backoff { attempt: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Returns a random delay between half and all of the current backoff, which doubles with
+    /// every attempt up to [`BACKOFF_MAX`].
+    pub fn next_delay(&mut self) -> Duration {
+        use rand::Rng;
+        let cap = BACKOFF_BASE
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(BACKOFF_MAX);
+        self.attempt = self.attempt.saturating_add(1);
+        rand::thread_rng().gen_range(cap / 2..=cap)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Endpoint;
+    use crate::types::PhishingMarkerStatus;
+
+    fn connector(base_url: &str) -> WebSocketConnector {
+        let mut client = HttpClient::new(Endpoint {
+            base_url: url::Url::parse(base_url).unwrap(),
+            ..Default::default()
+        });
+        client.set_access_token("token".into());
+        WebSocketConnector::from_url(&client, "user").unwrap()
+    }
+
+    #[test]
+    fn parses_entity_update() {
+        let message = WebSocket::parse(
+            r#"entityUpdate;{"_format":"0","eventBatchId":"MzS7bVt----0","eventBatchOwner":"MhRZoDK----0","eventBatch":[{"_id":"MzS7bVu----0","application":"tutanota","type":"Mail","instanceListId":"MhRZvLp----0","instanceId":"MzS7bVq----0","operation":"0"}]}"#,
+        );
+        let Some(Ok(WebSocketMessage::EntityUpdate(update))) = message else {
+            panic!("unexpected message {:?}", message);
+        };
+        assert_eq!(update.event_batch_owner, "MhRZoDK----0");
+        assert_eq!(update.event_batch[0].operation, OperationType::Create);
+    }
+
+    #[test]
+    fn parses_unread_counter_update() {
+        let message = WebSocket::parse(
+            r#"unreadCounterUpdate;{"_format":"0","mailGroup":"MhRZoDK----0","counterValues":[{"_id":"MzS7bW0----0","count":"3","counterId":"MhRZvLp----0"}]}"#,
+        );
+        let Some(Ok(WebSocketMessage::UnreadCounterUpdate(update))) = message else {
+            panic!("unexpected message {:?}", message);
+        };
+        assert_eq!(update.mail_group, "MhRZoDK----0");
+        assert_eq!(update.counter_values[0].counter_id, "MhRZvLp----0");
+        assert_eq!(update.counter_values[0].count, 3);
+    }
+
+    #[test]
+    fn parses_leader_status() {
+        let message = WebSocket::parse(r#"leaderStatus;{"_format":"0","leaderStatus":true}"#);
+        let Some(Ok(WebSocketMessage::LeaderStatus(status))) = message else {
+            panic!("unexpected message {:?}", message);
+        };
+        assert!(status.leader_status);
+    }
+
+    #[test]
+    fn parses_phishing_markers() {
+        let message = WebSocket::parse(
+            r#"phishingMarkers;{"_format":"0","lastId":"MzS7bW1----0","markers":[{"_id":"MzS7bW2----0","marker":"0Hk1bWjYKN3Gk9Pmoo3rEuLrcbHbxYcmAVXFjEFC0TI","status":"0"}]}"#,
+        );
+        let Some(Ok(WebSocketMessage::PhishingMarkers(markers))) = message else {
+            panic!("unexpected message {:?}", message);
+        };
+        assert_eq!(markers.last_id, "MzS7bW1----0");
+        assert_eq!(markers.markers[0].status, PhishingMarkerStatus::Active);
+    }
+
+    #[test]
+    fn ignores_unknown_messages() {
+        assert!(WebSocket::parse(r#"websocketAccount;{"_format":"0"}"#).is_none());
+        assert!(WebSocket::parse("no separator").is_none());
+        assert!(matches!(
+            WebSocket::parse("leaderStatus;{}"),
+            Some(Err(Error::Deserialization(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejected_handshake_is_session_expired() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        for status in ["401 Unauthorized", "440 Login Timeout"] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            });
+            let connector = connector(&format!("http://{}", addr));
+            assert!(matches!(
+                connector.connect().await,
+                Err(Error::SessionExpired)
+            ));
+        }
+    }
+
+    #[test]
+    fn maps_http_schemes_to_websocket_schemes() {
+        assert_eq!(connector("https://app.tuta.com").url.scheme(), "wss");
+        assert_eq!(connector("http://localhost:9000").url.scheme(), "ws");
+    }
+
+    #[test]
+    fn keeps_base_url_path_prefix() {
+        assert_eq!(connector("http://localhost/tuta").url.path(), "/tuta/event");
+        let client = HttpClient::new(Endpoint {
+            base_url: url::Url::parse("http://localhost/tuta/").unwrap(),
+            ..Default::default()
+        });
+        assert_eq!(
+            client.url("/rest/sys/saltservice").unwrap().path(),
+            "/tuta/rest/sys/saltservice"
+        );
     }
 }